@@ -1,56 +1,53 @@
-use nalgebra::{DMatrix, DVector, SVD};
-// complete honesty here, i did not write this code
-// as of the time of me writing this, i don't know a lot about matrix algebra.
-// so i wasn't able to write my own implementation by hand to solve AX = B for quadratic regression.
+use crate::regression::{Polynomial, RegressionModel};
+// these are the quadratic versions of what's in regression, kept for the callers that only need the quadratic.
+// fitting once with RegressionModel is cheaper when you need more than one of them
 
 /// Returns three coefficients
 pub fn quadratic_regression(
-    x_values: &Vec<f64>,
-    y_values: &Vec<f64>,
+    x_values: &[f64],
+    y_values: &[f64],
 ) -> Result<(f64, f64, f64), Box<dyn std::error::Error>> {
-    let n = x_values.len();
-
-    let x_matrix = DMatrix::from_fn(n, 3, |i, j| x_values[i].powi(2 - j as i32));
-    let y_vector = DVector::from_vec(y_values.to_vec());
-
-    let svd = SVD::new(x_matrix, true, true);
-    let result = svd.solve(&y_vector, nalgebra::convert(1.0e-10))?;
-
-    Ok((result[0], result[1], result[2]))
+    let c = Polynomial::fit(x_values, y_values, 2)?.coefficients;
+    Ok((c[0], c[1], c[2]))
 }
-// TODO: implement testing for this function
 
 pub fn generate_residuals(
-    x_values: &Vec<f64>,
-    y_values: &Vec<f64>,
+    x_values: &[f64],
+    y_values: &[f64],
 ) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
-    let r = quadratic_regression(x_values, y_values)?;
-    let predicted_y = x_values
-        .iter()
-        .map(|x| (r.0 * (x * x)) + (r.1 * x) + r.2)
-        .collect::<Vec<_>>();
-
-    Ok(y_values
-        .iter()
-        .zip(predicted_y)
-        .collect::<Vec<(_, _)>>()
-        .iter()
-        .map(|(actual, predicted)| **actual - *predicted)
-        .collect::<Vec<_>>())
+    Ok(Polynomial::fit(x_values, y_values, 2)?.residuals(x_values, y_values))
 }
 // you're filled with determination!!
 pub fn determination(
-    x_values: &Vec<f64>,
-    y_values: &Vec<f64>,
+    x_values: &[f64],
+    y_values: &[f64],
 ) -> Result<f64, Box<dyn std::error::Error>> {
-    let sse: f64 = generate_residuals(x_values, y_values)?
-        .iter()
-        .map(|y| y.powi(2))
-        .sum();
-    let mean = y_values.iter().sum::<f64>() / y_values.len() as f64;
-    let sst: f64 = y_values.iter().map(|y| (*y - mean).powi(2)).sum();
+    Ok(Polynomial::fit(x_values, y_values, 2)?.r_squared(x_values, y_values))
+}
+// standard deviation of the residuals around the fitted curve.
+// divides by n - 3 because the quadratic eats three degrees of freedom
+pub fn residual_standard_error(
+    x_values: &[f64],
+    y_values: &[f64],
+) -> Result<f64, Box<dyn std::error::Error>> {
+    let model = Polynomial::fit(x_values, y_values, 2)?;
+    Ok(model_standard_error(&model, x_values, y_values))
+}
 
-    Ok(1.0 - (sse / sst))
+/// Standard deviation of the residuals around any fitted model, over the points it's defined at.
+/// Every coefficient costs a degree of freedom.
+pub fn model_standard_error(
+    model: &dyn RegressionModel,
+    x_values: &[f64],
+    y_values: &[f64],
+) -> f64 {
+    let residuals: Vec<f64> = model
+        .residuals(x_values, y_values)
+        .into_iter()
+        .filter(|r| r.is_finite())
+        .collect();
+    let sse: f64 = residuals.iter().map(|r| r.powi(2)).sum();
+    (sse / (residuals.len() as f64 - model.coefficients().len() as f64)).sqrt()
 }
 // simple util function to round off some extra precision for testing
 pub fn to_precision(value: f64, decimal_digits: u32) -> f64 {
@@ -97,8 +94,8 @@ mod tests {
             12.55, 15.61, 10.20, 11.77, 10.24, 9.84, 8.07, 11.63, 12.82, 15.85,
         ];
         let y2 = [9.0, 5.7, 6.5, 3.3, 1.9, 0.6, 1.2, 2.6, 5.3, 7.8];
-        let res = super::determination(&x_values, &y1.to_vec()).unwrap();
-        let res2 = super::determination(&x_values, &y2.to_vec()).unwrap();
+        let res = super::determination(&x_values, &y1).unwrap();
+        let res2 = super::determination(&x_values, &y2).unwrap();
         let rounded = to_precision(res.sqrt(), 4);
         let rounded2 = to_precision(res2, 3);
 
@@ -126,9 +123,8 @@ mod tests {
     fn residual_standard_deviation() {
         let x = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
         let y = vec![10.0, 8.0, 6.0, 4.0, 2.0, 1.0, 3.0, 5.0, 7.0, 10.0];
-        let residuals = super::generate_residuals(&x, &y).unwrap();
-        let stdev = super::standard_deviation(&residuals);
-        assert_eq!(stdev, 0.802);
+        let stdev = super::residual_standard_error(&x, &y).unwrap();
+        assert_eq!(to_precision(stdev, 3), 0.802);
     }
     #[test]
     fn residuals() {
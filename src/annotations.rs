@@ -0,0 +1,218 @@
+// Comment annotations
+// lichess (and most other sites) put extra data in move comments as commands like
+// `{ [%eval 0.17] [%clk 0:00:30] }`. this finds the commands and parses their values.
+
+use std::{error::Error, fmt};
+
+/// Every `[%name value]` command in a comment, as `(name, value)`.
+/// An unterminated command runs to the end of the comment.
+pub fn commands(comment: &str) -> impl Iterator<Item = (&str, &str)> {
+    comment.split("[%").skip(1).map(|command| {
+        let command = command.split(']').next().unwrap_or_default().trim();
+        command.split_once(' ').unwrap_or((command, ""))
+    })
+}
+
+/// Why a clock value couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClockError {
+    /// The command had no value, e.g. `[%clk]`.
+    Missing,
+    /// The value isn't `h:mm:ss` with an optional fraction of a second.
+    Malformed(String),
+    /// Minutes or seconds of 60 or more, or a clock too long to count in milliseconds.
+    OutOfRange(String),
+}
+
+impl fmt::Display for ClockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockError::Missing => write!(f, "clock command without a value"),
+            ClockError::Malformed(value) => write!(f, "malformed clock value: {}", value),
+            ClockError::OutOfRange(value) => write!(f, "clock value out of range: {}", value),
+        }
+    }
+}
+
+impl Error for ClockError {}
+
+/// Parse a clock value written as `h:mm:ss` or `h:mm:ss.f` into milliseconds.
+/// Up to three digits of the fraction are kept.
+pub fn parse_clock(value: &str) -> Result<i32, ClockError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(ClockError::Missing);
+    }
+    let malformed = || ClockError::Malformed(value.to_string());
+
+    let (time, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let mut units = time.split(':');
+    let (Some(hours), Some(minutes), Some(seconds), None) =
+        (units.next(), units.next(), units.next(), units.next())
+    else {
+        return Err(malformed());
+    };
+    let number = |digits: &str, max_len: usize| {
+        if digits.is_empty()
+            || digits.len() > max_len
+            || !digits.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(malformed());
+        }
+        digits.parse::<i32>().map_err(|_| malformed())
+    };
+    let hours = number(hours, 4)?;
+    let minutes = number(minutes, 2)?;
+    let seconds = number(seconds, 2)?;
+    if minutes >= 60 || seconds >= 60 {
+        return Err(ClockError::OutOfRange(value.to_string()));
+    }
+    let millis = if value.contains('.') {
+        // "3" is 300ms, "25" is 250ms, anything past milliseconds is dropped
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(malformed());
+        }
+        let digits = &fraction[..fraction.len().min(3)];
+        number(digits, 3)? * 10i32.pow(3 - digits.len() as u32)
+    } else {
+        0
+    };
+
+    // 4 digits of hours is more than an i32 of milliseconds can hold
+    hours
+        .checked_mul(3_600_000)
+        .and_then(|ms| ms.checked_add((minutes * 60 + seconds) * 1000 + millis))
+        .ok_or_else(|| ClockError::OutOfRange(value.to_string()))
+}
+
+/// An engine evaluation from white's point of view, as in `[%eval 0.34]` or `[%eval #-3]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eval {
+    Centipawns(i32),
+    /// Mate in this many moves, negative when black is the one mating.
+    Mate(i32),
+}
+
+// evaluations are capped here, like lichess does when it works out centipawn loss,
+// so a won position losing a few pawns doesn't count as a huge mistake
+const MAX_CENTIPAWNS: i32 = 1000;
+
+impl Eval {
+    /// The evaluation in centipawns, capped at ±10 pawns. Mates count as the cap.
+    pub fn centipawns(&self) -> i32 {
+        match *self {
+            Eval::Centipawns(cp) => cp.clamp(-MAX_CENTIPAWNS, MAX_CENTIPAWNS),
+            Eval::Mate(moves) if moves < 0 => -MAX_CENTIPAWNS,
+            Eval::Mate(_) => MAX_CENTIPAWNS,
+        }
+    }
+}
+
+impl fmt::Display for Eval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Eval::Centipawns(cp) => write!(f, "{:.2}", cp as f64 / 100.0),
+            Eval::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+/// An evaluation that isn't a number of pawns or `#` and a number of moves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError(pub String);
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed evaluation: {}", self.0)
+    }
+}
+
+impl Error for EvalError {}
+
+/// Parse an evaluation in pawns (`0.34`, `-1.5`) or a mate (`#3`, `#-3`).
+/// Lichess sometimes adds the search depth after a comma (`0.34,20`), which is ignored.
+pub fn parse_eval(value: &str) -> Result<Eval, EvalError> {
+    let value = value.trim();
+    let eval = value.split(',').next().unwrap_or_default();
+    let error = || EvalError(value.to_string());
+    if let Some(moves) = eval.strip_prefix('#') {
+        return moves.parse().map(Eval::Mate).map_err(|_| error());
+    }
+    let pawns: f64 = eval.parse().map_err(|_| error())?;
+    if !pawns.is_finite() {
+        return Err(error());
+    }
+    Ok(Eval::Centipawns((pawns * 100.0).round() as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{commands, parse_clock, parse_eval, ClockError, Eval};
+
+    #[test]
+    fn finds_commands() {
+        let found: Vec<_> = commands(" [%eval 0.17] [%clk 0:00:30] ").collect();
+        assert_eq!(found, vec![("eval", "0.17"), ("clk", "0:00:30")]);
+        let found: Vec<_> = commands("good move! [%clk]").collect();
+        assert_eq!(found, vec![("clk", "")]);
+        let found: Vec<_> = commands("[%clk 0:01:00").collect();
+        assert_eq!(found, vec![("clk", "0:01:00")]);
+        assert_eq!(commands("no commands here").count(), 0);
+    }
+
+    #[test]
+    fn clocks() {
+        assert_eq!(parse_clock("0:0:0"), Ok(0));
+        assert_eq!(parse_clock("00:00:00"), Ok(0));
+        assert_eq!(parse_clock("0:0:1"), Ok(1000));
+        assert_eq!(parse_clock("0:1:01"), Ok(61_000));
+        assert_eq!(parse_clock("01:01:01"), Ok(3_661_000));
+        assert_eq!(parse_clock("0:00:05.3"), Ok(5_300));
+        assert_eq!(parse_clock("0:00:05.25"), Ok(5_250));
+        assert_eq!(parse_clock("0:00:05.1234"), Ok(5_123));
+    }
+
+    #[test]
+    fn clock_errors() {
+        assert_eq!(parse_clock(""), Err(ClockError::Missing));
+        for value in [
+            "0:00",
+            "0:00:00:00",
+            "a:00:00",
+            "0:00:5x",
+            "0:00:05.",
+            "0:00:05.123x",
+            "-1:00:00",
+        ] {
+            assert_eq!(
+                parse_clock(value),
+                Err(ClockError::Malformed(value.to_string()))
+            );
+        }
+        for value in ["0:60:00", "9999:00:00", "596:31:23.648"] {
+            assert_eq!(
+                parse_clock(value),
+                Err(ClockError::OutOfRange(value.to_string()))
+            );
+        }
+        // the longest clock that still fits
+        assert_eq!(parse_clock("596:31:23.647"), Ok(i32::MAX));
+    }
+
+    #[test]
+    fn evals() {
+        assert_eq!(parse_eval("0.34"), Ok(Eval::Centipawns(34)));
+        assert_eq!(parse_eval("-1.5"), Ok(Eval::Centipawns(-150)));
+        assert_eq!(parse_eval("0.34,20"), Ok(Eval::Centipawns(34)));
+        assert_eq!(parse_eval("#-3"), Ok(Eval::Mate(-3)));
+        assert_eq!(parse_eval("#12"), Ok(Eval::Mate(12)));
+        assert!(parse_eval("").is_err());
+        assert!(parse_eval("#").is_err());
+        assert!(parse_eval("inf").is_err());
+
+        assert_eq!(Eval::Centipawns(-150).to_string(), "-1.50");
+        assert_eq!(Eval::Mate(-3).to_string(), "#-3");
+        assert_eq!(Eval::Centipawns(2500).centipawns(), 1000);
+        assert_eq!(Eval::Mate(-3).centipawns(), -1000);
+    }
+}
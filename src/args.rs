@@ -1,4 +1,16 @@
-use clap::Parser;
+use chess_analysis::export::ExportFormat;
+use chess_analysis::filter::{
+    time_control_filter, And, BothSides, Date, DateRange, EventKind, EventKindIs,
+    ExcludeTerminations, GameHeaders, PlayerIs, RatedIs, RatingGap, RatingMode, RatingRange,
+    SideFilter, Termination, VariantIs,
+};
+use chess_analysis::moves::Color;
+use chess_analysis::plots::plotter::PlotOptions;
+use chess_analysis::quality::JudgementMethod;
+use chess_analysis::reader::{BadClocks, ReaderConfig};
+use chess_analysis::regression::{Criterion, ModelKind};
+use clap::{Parser, Subcommand};
+use std::sync::Arc;
 
 /// Analyze and graph time-related information from one or more chess game(s). Written in pure Rust!
 /// NOTE: If you have a lot of games, it's not recommended to run without any arguments, but then again,
@@ -6,27 +18,326 @@ use clap::Parser;
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Path to PGN file. The PGN file may contain one or more games.
-    pub input: String,
-    /// Output path. Creates a directory titled with the UNIX timestamp in this directory.
-    #[arg(short = 'o', long)]
-    pub output: String,
+    #[command(subcommand)]
+    pub command: Command,
+}
 
-    /// The time mode to choose from. Formatted as seconds+seconds (NOT minutes+seconds as on lichess). Refer to lichess for options
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Read games and print a summary, optionally saving the dataset or exporting the moves.
+    Collect(CollectArgs),
+    /// Print the regression analysis (and percentiles, if asked for).
+    Analyze(AnalyzeArgs),
+    /// Create graphs of the data.
+    Plot(PlotArgs),
+    /// Print summary statistics of the data.
+    Report(ReportArgs),
+}
+
+/// Where the games come from and which ones to keep. Shared by every subcommand.
+#[derive(Parser, Debug, Clone)]
+pub struct DataArgs {
+    /// Path(s) to PGN files or glob patterns such as "games/*.pgn.zst". Each file may contain one or more games.
+    /// May be compressed with zstd, bzip2 or gzip (like the lichess database). Use - to read from stdin.
+    /// Move times are read from [%clk] comments, or rebuilt from [%emt] (elapsed move time) comments when there are none.
+    /// A single dataset saved with `collect --output` may be given instead, which skips parsing entirely
+    /// (the filters below were applied when it was collected and can't be given with it).
+    #[arg(required = true, num_args = 1..)]
+    pub input: Vec<String>,
+
+    /// The time control(s) to read. Formatted as seconds+seconds (NOT minutes+seconds as on lichess). Refer to lichess for options.
+    /// Accepts a comma separated list (180+0,180+2), ranges of base time and increment (180+0..300+5)
+    /// and speed categories (ultrabullet, bullet, blitz, rapid, classical). Reads every time control if not given.
     #[arg(short = 'c', long)]
-    pub time_control: String,
+    pub time_control: Option<String>,
+
+    /// Measure time left and time taken as a percentage of each game's starting clock instead of seconds,
+    /// so games with different time controls can be pooled.
+    #[arg(long)]
+    pub normalize: bool,
 
     /// The maximum number of games to collect data from.
     /// (NOTE: The reader will read games past the limit but will not record any data from them, due to the nature of pgn-reader)
     #[arg(long)]
     pub max_games: Option<usize>,
 
-    /// The minimum average rating between both players in games to collect from
+    /// What to do with games that have a clock comment that can't be read: skip the whole game,
+    /// or keep it and flag it (the bad clock is ignored, so it doesn't affect the moves around it).
+    #[arg(long, value_enum, default_value_t = BadClocks::Skip)]
+    pub bad_clocks: BadClocks,
+
+    /// Number of threads used to read games. Defaults to the number of available CPU cores.
+    #[arg(short = 'j', long)]
+    pub threads: Option<usize>,
+
+    /// The minimum rating in games to collect from. Which rating is checked depends on --rating-mode.
     #[arg(short = 'm', long)]
     pub min_rating: Option<i32>,
     #[arg(short = 'M', long)]
-    /// The maxmimum average rating between both players in games to collect from
+    /// The maximum rating in games to collect from. Which rating is checked depends on --rating-mode.
     pub max_rating: Option<i32>,
+    /// Check the rating limits against the average of both players, both players individually, or at least one of them.
+    #[arg(long, value_enum, default_value_t = RatingMode::Both)]
+    pub rating_mode: RatingMode,
+    /// Only record the moves of the player(s) whose own rating is within the rating limits.
+    /// Useful with --rating-mode either or average to study one rating band in lopsided games.
+    #[arg(long)]
+    pub rated_side_only: bool,
+
+    /// Only record the moves of this color.
+    #[arg(long, value_enum)]
+    pub color: Option<Color>,
+
+    /// The minimum rating difference between the two players.
+    #[arg(long)]
+    pub min_rating_gap: Option<i32>,
+    /// The maximum rating difference between the two players.
+    #[arg(long)]
+    pub max_rating_gap: Option<i32>,
+
+    /// Only read games played on or after this date (UTCDate, or Date if there isn't one).
+    /// Formatted as 2023-10-31, or 2023-10 / 2023 for the start of a month or year.
+    #[arg(long)]
+    pub from_date: Option<String>,
+    /// Only read games played on or before this date. 2023-10 / 2023 mean the end of that month or year.
+    #[arg(long)]
+    pub to_date: Option<String>,
+    /// Only read rated games (true) or casual games (false), going by the Event header.
+    #[arg(long)]
+    pub rated: Option<bool>,
+    /// Only read games paired this way (comma separated), going by the Event header.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub event_kind: Vec<EventKind>,
+    /// Only read games of these variants (comma separated, e.g. standard). Games without a Variant header are standard.
+    #[arg(long, value_delimiter = ',')]
+    pub variant: Vec<String>,
+    /// Skip games that ended this way (comma separated), e.g. abandoned,rules-infraction.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub exclude_termination: Vec<Termination>,
+
+    /// Track these players (comma separated usernames, not case sensitive).
+    /// The report compares each of them with everyone else in the data.
+    #[arg(long, value_delimiter = ',')]
+    pub player: Vec<String>,
+    /// Track the players listed in this file, one username per line.
+    #[arg(long)]
+    pub players_file: Option<String>,
+    /// Only read games of the tracked players, and only record their moves.
+    #[arg(long)]
+    pub only_players: bool,
+}
+
+impl DataArgs {
+    /// The flags given that only apply while reading PGNs. A saved dataset was already filtered
+    /// when it was collected, and doesn't keep the headers to filter it again.
+    pub fn reading_flags(&self) -> Vec<&'static str> {
+        [
+            ("--time-control", self.time_control.is_some()),
+            ("--max-games", self.max_games.is_some()),
+            ("--bad-clocks", self.bad_clocks != BadClocks::default()),
+            ("--min-rating", self.min_rating.is_some()),
+            ("--max-rating", self.max_rating.is_some()),
+            ("--rating-mode", self.rating_mode != RatingMode::default()),
+            ("--rated-side-only", self.rated_side_only),
+            ("--color", self.color.is_some()),
+            ("--min-rating-gap", self.min_rating_gap.is_some()),
+            ("--max-rating-gap", self.max_rating_gap.is_some()),
+            ("--from-date", self.from_date.is_some()),
+            ("--to-date", self.to_date.is_some()),
+            ("--rated", self.rated.is_some()),
+            ("--event-kind", !self.event_kind.is_empty()),
+            ("--variant", !self.variant.is_empty()),
+            (
+                "--exclude-termination",
+                !self.exclude_termination.is_empty(),
+            ),
+            ("--player", !self.player.is_empty()),
+            ("--players-file", self.players_file.is_some()),
+            ("--only-players", self.only_players),
+        ]
+        .into_iter()
+        .filter(|(_, given)| *given)
+        .map(|(flag, _)| flag)
+        .collect()
+    }
+
+    /// The reader settings these options describe. Fails if the time control can't be parsed.
+    pub fn reader_config(&self) -> Result<ReaderConfig, Box<dyn std::error::Error>> {
+        let mut filter = And(Vec::new());
+        if let Some(time_control) = &self.time_control {
+            filter.0.push(time_control_filter(time_control)?);
+        }
+        let rating = RatingRange {
+            min: self.min_rating,
+            max: self.max_rating,
+            mode: self.rating_mode,
+        };
+        filter.0.push(Arc::new(rating));
+        if self.min_rating_gap.is_some() || self.max_rating_gap.is_some() {
+            filter.0.push(Arc::new(RatingGap {
+                min: self.min_rating_gap,
+                max: self.max_rating_gap,
+            }));
+        }
+        if self.from_date.is_some() || self.to_date.is_some() {
+            filter.0.push(Arc::new(DateRange {
+                from: self
+                    .from_date
+                    .as_deref()
+                    .map(|d| Date::parse_bound(d, false))
+                    .transpose()?,
+                to: self
+                    .to_date
+                    .as_deref()
+                    .map(|d| Date::parse_bound(d, true))
+                    .transpose()?,
+            }));
+        }
+        if let Some(rated) = self.rated {
+            filter.0.push(Arc::new(RatedIs(rated)));
+        }
+        if !self.event_kind.is_empty() {
+            filter
+                .0
+                .push(Arc::new(EventKindIs(self.event_kind.clone())));
+        }
+        if !self.variant.is_empty() {
+            filter.0.push(Arc::new(VariantIs(self.variant.clone())));
+        }
+        if !self.exclude_termination.is_empty() {
+            filter.0.push(Arc::new(ExcludeTerminations(
+                self.exclude_termination.clone(),
+            )));
+        }
+        let mut players = self.player.clone();
+        if let Some(path) = &self.players_file {
+            let file = std::fs::read_to_string(path)?;
+            players.extend(
+                file.lines()
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from),
+            );
+        }
+        let players = PlayerIs(players);
+        if self.only_players {
+            if players.0.is_empty() {
+                return Err("--only-players needs --player or --players-file".into());
+            }
+            filter.0.push(Arc::new(players.clone()));
+        }
+        let mut sides: Arc<dyn SideFilter> = if self.rated_side_only {
+            Arc::new(rating)
+        } else {
+            Arc::new(BothSides)
+        };
+        if let Some(color) = self.color {
+            let inner = sides;
+            sides = Arc::new(move |headers: &GameHeaders, c: Color| {
+                c == color && inner.keep(headers, c)
+            });
+        }
+        if self.only_players {
+            let inner = sides;
+            let only = players.clone();
+            sides = Arc::new(move |headers: &GameHeaders, c: Color| {
+                only.keep(headers, c) && inner.keep(headers, c)
+            });
+        }
+        Ok(ReaderConfig {
+            max_games: self.max_games,
+            filter: Arc::new(filter),
+            sides,
+            bad_clocks: self.bad_clocks,
+            players,
+        })
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct CollectArgs {
+    #[command(flatten)]
+    pub data: DataArgs,
+
+    /// Save the collected moves to a binary dataset file, along with the filters used.
+    /// Pass that file as the input to the other subcommands to skip parsing the PGN again.
+    #[arg(short = 'o', long)]
+    pub output: Option<String>,
+
+    /// Write every recorded move and its game metadata to this file.
+    #[arg(short = 'e', long)]
+    pub export: Option<String>,
+    /// Format of the exported file. Guessed from the extension if not given (.jsonl for JSON Lines, otherwise CSV).
+    #[arg(long, value_enum)]
+    pub export_format: Option<ExportFormat>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    pub data: DataArgs,
+
+    /// Print this percentile of time left.
+    #[arg(long)]
+    pub x_percentile: Option<i32>,
+    /// Print this percentile of time taken.
+    #[arg(long)]
+    pub y_percentile: Option<i32>,
+    /// The regression model to fit: linear, quadratic, cubic, poly<degree> (e.g. poly4), log, exp or power.
+    /// Leave out to rank the candidates and use the best one.
+    #[arg(long)]
+    pub model: Option<ModelKind>,
+    /// What the candidate models are ranked by when --model is left out.
+    #[arg(long, value_enum, default_value_t = Criterion::Cv)]
+    pub rank_by: Criterion,
+    /// Number of folds (of whole games) for cross-validating the candidate models.
+    #[arg(long, default_value_t = 5)]
+    pub folds: usize,
+    /// Confidence level of the coefficient and bootstrap intervals.
+    #[arg(long, default_value_t = 0.95, value_parser = confidence_level)]
+    pub confidence: f64,
+    /// Percentiles of time taken to fit quantile regression curves for.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = [10.0, 50.0, 90.0],
+        value_parser = percentile
+    )]
+    pub quantiles: Vec<f64>,
+    /// Resample whole games this many times for bootstrap intervals of the statistics.
+    #[arg(long)]
+    pub bootstrap: Option<usize>,
+    /// Seed for the bootstrap resampling. The same seed gives the same intervals.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+    /// Also run the regression for white's and black's moves separately.
+    #[arg(long)]
+    pub by_color: bool,
+    /// Print the rate of inaccuracies, mistakes and blunders by time left. Needs games with [%eval] comments.
+    #[arg(long)]
+    pub errors: bool,
+    /// How moves are judged for --errors.
+    #[arg(long, value_enum, default_value_t = JudgementMethod::WinChance)]
+    pub judgement: JudgementMethod,
+    /// Print time taken by move number and game phase, for all moves and split by time control and rating band.
+    #[arg(long)]
+    pub by_move: bool,
+    /// Moves past this move number are counted together with it.
+    #[arg(long, default_value_t = 60)]
+    pub max_move: u16,
+    /// Width of the rating bands used by --by-move.
+    #[arg(long, default_value_t = 200)]
+    pub rating_band: i32,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct PlotArgs {
+    #[command(flatten)]
+    pub data: DataArgs,
+
+    /// Output path. Creates a directory titled with the UNIX timestamp in this directory.
+    #[arg(short = 'o', long)]
+    pub output: String,
 
     #[arg(short, long)]
     /// Set the resolution of the output images (1:1 ratio). Default is 1000 pixels.
@@ -38,21 +349,117 @@ pub struct Args {
     #[arg(short = 'a', long)]
     pub averages: bool,
 
+    /// Enable this option to draw separate average lines for white and black on the averages graph
+    #[arg(long)]
+    pub by_color: bool,
+
+    /// Enable this option to create a line graph of average TTM and time left by move number
+    #[arg(long)]
+    pub by_move: bool,
+    /// Moves past this move number are counted together with it on the move number graph.
+    #[arg(long, default_value_t = 60)]
+    pub max_move: u16,
+
+    /// Enable this option to create a line graph of inaccuracy, mistake and blunder rates. Needs games with [%eval] comments.
+    #[arg(long)]
+    pub errors: bool,
+    /// How moves are judged for --errors.
+    #[arg(long, value_enum, default_value_t = JudgementMethod::WinChance)]
+    pub judgement: JudgementMethod,
+
     /// Enable this option to create a residual graph
     #[arg(long)]
     pub residuals: bool,
 
+    /// Enable this option to create histograms of time left and time taken
     #[arg(long)]
     pub one_var: bool,
+    /// Enable this option to draw the regression curve over the averages and all TTMs graphs
     #[arg(long)]
     pub overlay_regression: bool,
+    /// The regression model to draw and to take the residuals from:
+    /// linear, quadratic, cubic, poly<degree> (e.g. poly4), log, exp or power.
+    /// Leave out to use the best ranked candidate.
+    #[arg(long)]
+    pub model: Option<ModelKind>,
+    /// What the candidate models are ranked by when --model is left out.
+    #[arg(long, value_enum, default_value_t = Criterion::Cv)]
+    pub rank_by: Criterion,
+    /// Number of folds (of whole games) for cross-validating the candidate models.
+    #[arg(long, default_value_t = 5)]
+    pub folds: usize,
+    /// Enable this option to draw a fan of quantile regression curves on the averages graph
+    #[arg(long)]
+    pub quantile_fan: bool,
+    /// Percentiles of time taken drawn by --quantile-fan.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_values_t = [10.0, 50.0, 90.0],
+        value_parser = percentile
+    )]
+    pub quantiles: Vec<f64>,
+    /// Enable this option to shade confidence and prediction bands around the regression curve
+    #[arg(long)]
+    pub bands: bool,
+    /// Confidence level of the bands.
+    #[arg(long, default_value_t = 0.95, value_parser = confidence_level)]
+    pub confidence: f64,
 
     #[arg(short, long)]
     /// Enable this option to output SVG files in addition to the default PNG output. (the better kind)
     pub svg: bool,
+}
 
-    #[arg(long)]
-    pub x_percentile: Option<i32>,
-    #[arg(long)]
-    pub y_percentile: Option<i32>,
+impl PlotArgs {
+    pub fn plot_options(&self) -> PlotOptions {
+        PlotOptions {
+            output: self.output.clone(),
+            resolution: self.resolution,
+            all: self.all,
+            averages: self.averages,
+            by_color: self.by_color,
+            by_move: self.by_move,
+            max_move: self.max_move,
+            errors: self.errors,
+            judgement: self.judgement,
+            residuals: self.residuals,
+            one_var: self.one_var,
+            overlay_regression: self.overlay_regression,
+            model: self.model,
+            rank_by: self.rank_by,
+            folds: self.folds,
+            quantile_fan: self.quantile_fan,
+            quantiles: self.quantiles.clone(),
+            bands: self.bands,
+            confidence: self.confidence,
+            svg: self.svg,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ReportArgs {
+    #[command(flatten)]
+    pub data: DataArgs,
+}
+
+// anything outside (0, 1) would quietly turn the intervals into nonsense
+fn confidence_level(value: &str) -> Result<f64, String> {
+    let confidence: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if confidence > 0.0 && confidence < 1.0 {
+        Ok(confidence)
+    } else {
+        Err("must be between 0 and 1, e.g. 0.95".to_string())
+    }
+}
+
+// the 0th and 100th percentiles have no curve, they'd sit below or above every move
+fn percentile(value: &str) -> Result<f64, String> {
+    let percentile: f64 = value.parse().map_err(|e| format!("{}", e))?;
+    if percentile > 0.0 && percentile < 100.0 {
+        Ok(percentile)
+    } else {
+        Err("must be between 0 and 100, e.g. 90".to_string())
+    }
 }
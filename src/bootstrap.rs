@@ -0,0 +1,256 @@
+// Game-clustered bootstrap
+// moves from the same game aren't independent (same players, same clock, same position), so
+// standard errors that treat every move as its own sample come out way too small.
+// resampling whole games instead of moves keeps all of that together.
+
+use std::collections::HashMap;
+use std::thread;
+
+/// Small seeded random number generator (splitmix64), so a bootstrap can be repeated exactly.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 up to (not including) `n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        // the modulo bias is nothing next to the number of games
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Indices of the moves of each game, in the order the games first show up.
+pub fn clusters(game_ids: &[usize]) -> Vec<Vec<usize>> {
+    let mut index = HashMap::new();
+    let mut games: Vec<Vec<usize>> = Vec::new();
+    for (i, id) in game_ids.iter().enumerate() {
+        let game = *index.entry(id).or_insert_with(|| {
+            games.push(Vec::new());
+            games.len() - 1
+        });
+        games[game].push(i);
+    }
+    games
+}
+
+/// Percentile interval of one statistic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    /// The statistic on the actual data.
+    pub estimate: f64,
+    /// Standard deviation of the statistic over the resamples.
+    pub standard_error: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+/// Intervals for every statistic, and how many resamples they came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub resamples: usize,
+    pub intervals: Vec<Interval>,
+}
+
+/// How to bootstrap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bootstrap {
+    pub resamples: usize,
+    pub seed: u64,
+    /// Confidence level of the intervals, e.g. 0.95.
+    pub confidence: f64,
+}
+
+impl Bootstrap {
+    /// Resample whole games with replacement and work out `statistic` on each resample.
+    ///
+    /// `statistic` gets the time left and time taken of the resampled moves and returns any number
+    /// of values, always the same number. Resamples it returns `None` for (like one too small for a
+    /// regression) are skipped. Returns `None` if it doesn't work on the actual data either.
+    ///
+    /// Every resample gets its own generator seeded from `seed` and its index, so the results
+    /// don't depend on how the work got split between threads.
+    pub fn run<F>(
+        &self,
+        game_ids: &[usize],
+        x_values: &[f64],
+        y_values: &[f64],
+        statistic: F,
+    ) -> Option<Summary>
+    where
+        F: Fn(&[f64], &[f64]) -> Option<Vec<f64>> + Sync,
+    {
+        let estimates = statistic(x_values, y_values)?;
+        let games = clusters(game_ids);
+        if games.is_empty() {
+            return None;
+        }
+
+        let threads = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .clamp(1, self.resamples.max(1));
+        let samples: Vec<Vec<f64>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|thread| {
+                    let (games, statistic) = (&games, &statistic);
+                    scope.spawn(move || {
+                        (thread..self.resamples)
+                            .step_by(threads)
+                            .filter_map(|i| {
+                                let mut rng = Rng::new(self.seed.wrapping_add(i as u64));
+                                let (x, y) = resample(games, x_values, y_values, &mut rng);
+                                statistic(&x, &y)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("bootstrap thread panicked"))
+                .collect()
+        });
+
+        let alpha = 1.0 - self.confidence;
+        let intervals = estimates
+            .iter()
+            .enumerate()
+            .map(|(i, &estimate)| {
+                let mut values: Vec<f64> = samples
+                    .iter()
+                    .map(|sample| sample[i])
+                    .filter(|v| v.is_finite())
+                    .collect();
+                values.sort_by(f64::total_cmp);
+                let n = values.len() as f64;
+                let mean = values.iter().sum::<f64>() / n;
+                let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+                let quantile = |q: f64| {
+                    values
+                        .get(((n - 1.0) * q).round() as usize)
+                        .copied()
+                        .unwrap_or(f64::NAN)
+                };
+                Interval {
+                    estimate,
+                    standard_error: variance.sqrt(),
+                    low: quantile(alpha / 2.0),
+                    high: quantile(1.0 - alpha / 2.0),
+                }
+            })
+            .collect();
+        Some(Summary {
+            resamples: samples.len(),
+            intervals,
+        })
+    }
+}
+
+// as many games as there are, picked at random with replacement
+fn resample(
+    games: &[Vec<usize>],
+    x_values: &[f64],
+    y_values: &[f64],
+    rng: &mut Rng,
+) -> (Vec<f64>, Vec<f64>) {
+    let mut x = Vec::with_capacity(x_values.len());
+    let mut y = Vec::with_capacity(y_values.len());
+    for _ in 0..games.len() {
+        for &i in &games[rng.below(games.len())] {
+            x.push(x_values[i]);
+            y.push(y_values[i]);
+        }
+    }
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clusters, Bootstrap, Rng};
+    use crate::analysis::standard_deviation;
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn rng_and_clusters() {
+        let draws: Vec<usize> = {
+            let mut rng = Rng::new(7);
+            (0..100).map(|_| rng.below(10)).collect()
+        };
+        let mut rng = Rng::new(7);
+        assert_eq!(draws, (0..100).map(|_| rng.below(10)).collect::<Vec<_>>());
+        assert!(draws.iter().all(|d| *d < 10));
+        // every value comes up eventually
+        assert!((0..10).all(|d| draws.contains(&d)));
+
+        assert_eq!(
+            clusters(&[4, 4, 2, 4, 9]),
+            vec![vec![0, 1, 3], vec![2], vec![4]]
+        );
+    }
+
+    #[test]
+    fn clustered_mean() {
+        // 40 games of 10 moves, every game has its own pace, so moves within a game are alike
+        let game_ids: Vec<usize> = (0..400).map(|i| i / 10).collect();
+        let y_values: Vec<f64> = (0..400)
+            .map(|i| ((i / 10) * 37 % 40) as f64 / 4.0 + (i % 3) as f64 * 0.1)
+            .collect();
+        let x_values = vec![0.0; 400];
+        let bootstrap = Bootstrap {
+            resamples: 500,
+            seed: 42,
+            confidence: 0.95,
+        };
+        let statistic = |_: &[f64], y: &[f64]| Some(vec![mean(y)]);
+        let summary = bootstrap
+            .run(&game_ids, &x_values, &y_values, statistic)
+            .unwrap();
+        assert_eq!(summary.resamples, 500);
+        let interval = summary.intervals[0];
+        assert_eq!(interval.estimate, mean(&y_values));
+        assert!(interval.low < interval.estimate && interval.estimate < interval.high);
+        // much wider than if the 400 moves were independent
+        let naive = standard_deviation(&y_values) / 400f64.sqrt();
+        assert!(interval.standard_error > 2.0 * naive);
+
+        // the same seed gives the same intervals
+        assert_eq!(
+            bootstrap.run(&game_ids, &x_values, &y_values, statistic),
+            Some(summary)
+        );
+    }
+
+    #[test]
+    fn failed_statistics() {
+        let bootstrap = Bootstrap {
+            resamples: 50,
+            seed: 1,
+            confidence: 0.9,
+        };
+        let game_ids = [0, 0, 1, 2];
+        let values = [1.0, 2.0, 3.0, 4.0];
+        // resamples without the first game are skipped
+        let summary = bootstrap
+            .run(&game_ids, &values, &values, |x, _| {
+                x.contains(&1.0).then(|| vec![mean(x)])
+            })
+            .unwrap();
+        assert!(summary.resamples > 0 && summary.resamples < 50);
+        assert!(bootstrap
+            .run(&game_ids, &values, &values, |_, _| None)
+            .is_none());
+    }
+}
@@ -0,0 +1,426 @@
+// Binary dataset cache
+// parsing a big PGN takes a long time, so the moves collected by a GameReader can be saved to a
+// compact binary file and loaded again for later runs that only change a plot or stats flag.
+//
+// layout (all numbers little endian):
+//   magic, format version
+//   description of the filters the data was collected with, game limit, tracked players
+//   games analyzed, total games, moves analyzed, games with bad clocks,
+//   %clk/%emt checks and mismatches, largest starting clock
+//   number of moves, then one column after another (see MoveStore).
+//   a move's context is a byte of flags, the number of legal moves (0 when unknown)
+//   and the number of pieces (255 when unknown). the player column uses 65535 for untracked players
+
+use crate::annotations::Eval;
+use crate::filter::{BothSides, Described, PlayerIs};
+use crate::moves::{Color, GameResult, MoveContext, MoveStore, TimeControl};
+use crate::reader::{BadClocks, GameReader, ReaderConfig};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+const MAGIC: &[u8; 8] = b"CHESSTTM";
+const VERSION: u32 = 1;
+// written in place of a missing rating or limit
+const NONE: i64 = i64::MIN;
+
+/// Check the magic bytes to see if a file is a saved dataset rather than a PGN.
+pub fn is_dataset(path: &str) -> bool {
+    let mut magic = [0; 8];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok_and(|_| &magic == MAGIC)
+}
+
+/// Save everything a GameReader collected.
+pub fn save(game_reader: &GameReader, path: &Path) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write_dataset(game_reader, &mut w)?;
+    w.flush()
+}
+
+/// Load a saved dataset, along with the config it was collected with.
+pub fn load(path: &Path) -> io::Result<GameReader> {
+    read_dataset(&mut BufReader::new(File::open(path)?))
+}
+
+fn write_dataset<W: Write>(game_reader: &GameReader, w: &mut W) -> io::Result<()> {
+    let config = &game_reader.config;
+    w.write_all(MAGIC)?;
+    write_u32(w, VERSION)?;
+
+    write_str(w, &config.filter.description())?;
+    write_opt(w, config.max_games.map(|m| m as i64))?;
+    write_u32(w, config.players.0.len() as u32)?;
+    for player in &config.players.0 {
+        write_str(w, player)?;
+    }
+
+    write_u64(w, game_reader.games_analyzed as u64)?;
+    write_u64(w, game_reader.total_games as u64)?;
+    write_u64(w, game_reader.moves_analyzed as u64)?;
+    write_u64(w, game_reader.bad_clock_games as u64)?;
+    write_u64(w, game_reader.clock_checks as u64)?;
+    write_u64(w, game_reader.clock_mismatches as u64)?;
+    write_i32(w, game_reader.max_allowed_time)?;
+
+    let moves = &game_reader.moves;
+    write_u64(w, moves.len() as u64)?;
+    for &id in &moves.game_id {
+        write_u64(w, id as u64)?;
+    }
+    for &color in &moves.color {
+        w.write_all(&[color as u8])?;
+    }
+    for &ply in &moves.ply {
+        w.write_all(&ply.to_le_bytes())?;
+    }
+    for column in [
+        &moves.clock_before,
+        &moves.clock_after,
+        &moves.time_spent,
+        &moves.increment,
+    ] {
+        for &value in column {
+            write_i32(w, value)?;
+        }
+    }
+    for column in [&moves.white_elo, &moves.black_elo] {
+        for &elo in column {
+            write_opt(w, elo.map(i64::from))?;
+        }
+    }
+    for &result in &moves.result {
+        w.write_all(&[result as u8])?;
+    }
+    for tc in &moves.time_control {
+        write_i32(w, tc.base)?;
+        write_i32(w, tc.increment)?;
+    }
+    for column in [&moves.eval_before, &moves.eval] {
+        for &eval in column {
+            write_eval(w, eval)?;
+        }
+    }
+    for context in &moves.context {
+        write_context(w, context)?;
+    }
+    for &player in &moves.player {
+        w.write_all(&player.unwrap_or(u16::MAX).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_dataset<R: Read>(r: &mut R) -> io::Result<GameReader> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a chess-analysis dataset"));
+    }
+    let version = read_u32(r)?;
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "dataset version {} is not supported (expected {})",
+            version, VERSION
+        )));
+    }
+
+    let config = ReaderConfig {
+        // the filters themselves can't be saved, only what they were
+        filter: Arc::new(Described(read_str(r)?)),
+        max_games: read_opt(r)?.map(|v| v as usize),
+        players: PlayerIs(
+            (0..read_u32(r)?)
+                .map(|_| read_str(r))
+                .collect::<io::Result<_>>()?,
+        ),
+        // the moves were already picked when collecting
+        sides: Arc::new(BothSides),
+        bad_clocks: BadClocks::default(),
+    };
+    let mut game_reader = GameReader::new(config);
+
+    game_reader.games_analyzed = read_u64(r)? as usize;
+    game_reader.total_games = read_u64(r)? as usize;
+    game_reader.moves_analyzed = read_u64(r)? as usize;
+    game_reader.bad_clock_games = read_u64(r)? as usize;
+    game_reader.clock_checks = read_u64(r)? as usize;
+    game_reader.clock_mismatches = read_u64(r)? as usize;
+    game_reader.max_allowed_time = read_i32(r)?;
+
+    let n = read_u64(r)? as usize;
+    let mut moves = MoveStore::new();
+    moves.game_id = (0..n)
+        .map(|_| read_u64(r).map(|v| v as usize))
+        .collect::<io::Result<_>>()?;
+    moves.color = (0..n)
+        .map(|_| {
+            read_u8(r).map(|c| match c {
+                0 => Color::White,
+                _ => Color::Black,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    moves.ply = (0..n)
+        .map(|_| {
+            let mut buf = [0; 2];
+            r.read_exact(&mut buf).map(|_| u16::from_le_bytes(buf))
+        })
+        .collect::<io::Result<_>>()?;
+    moves.clock_before = read_i32s(r, n)?;
+    moves.clock_after = read_i32s(r, n)?;
+    moves.time_spent = read_i32s(r, n)?;
+    moves.increment = read_i32s(r, n)?;
+    moves.white_elo = read_elos(r, n)?;
+    moves.black_elo = read_elos(r, n)?;
+    moves.result = (0..n)
+        .map(|_| {
+            read_u8(r).map(|res| match res {
+                0 => GameResult::WhiteWins,
+                1 => GameResult::BlackWins,
+                2 => GameResult::Draw,
+                _ => GameResult::Unknown,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    moves.time_control = (0..n)
+        .map(|_| {
+            Ok(TimeControl {
+                base: read_i32(r)?,
+                increment: read_i32(r)?,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+    moves.eval_before = read_evals(r, n)?;
+    moves.eval = read_evals(r, n)?;
+    moves.context = (0..n).map(|_| read_context(r)).collect::<io::Result<_>>()?;
+    moves.player = (0..n)
+        .map(|_| {
+            let mut buf = [0; 2];
+            r.read_exact(&mut buf)?;
+            let player = u16::from_le_bytes(buf);
+            Ok((player != u16::MAX).then_some(player))
+        })
+        .collect::<io::Result<_>>()?;
+    game_reader.moves = moves;
+
+    Ok(game_reader)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_u32<W: Write>(w: &mut W, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+fn write_u64<W: Write>(w: &mut W, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+fn write_i32<W: Write>(w: &mut W, value: i32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+fn write_str<W: Write>(w: &mut W, value: &str) -> io::Result<()> {
+    write_u32(w, value.len() as u32)?;
+    w.write_all(value.as_bytes())
+}
+fn write_opt<W: Write>(w: &mut W, value: Option<i64>) -> io::Result<()> {
+    w.write_all(&value.unwrap_or(NONE).to_le_bytes())
+}
+// a tag (0 for none, 1 for centipawns, 2 for mate) followed by the number
+fn write_eval<W: Write>(w: &mut W, eval: Option<Eval>) -> io::Result<()> {
+    let (tag, value) = match eval {
+        None => (0, 0),
+        Some(Eval::Centipawns(cp)) => (1, cp),
+        Some(Eval::Mate(moves)) => (2, moves),
+    };
+    w.write_all(&[tag])?;
+    write_i32(w, value)
+}
+
+fn write_context<W: Write>(w: &mut W, context: &MoveContext) -> io::Result<()> {
+    let flags = [
+        context.capture,
+        context.check,
+        context.promotion,
+        context.castle,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0u8, |flags, (bit, &set)| flags | (set as u8) << bit);
+    w.write_all(&[flags])?;
+    // there's always at least one legal move (the one that was played), so 0 is free for unknown
+    w.write_all(&context.legal_moves.unwrap_or(0).to_le_bytes())?;
+    w.write_all(&[context.pieces.unwrap_or(u8::MAX)])
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+fn read_str<R: Read>(r: &mut R) -> io::Result<String> {
+    // the length comes from the file, so a corrupt one shouldn't get to allocate gigabytes
+    // before the read runs out
+    let len = read_u32(r)? as u64;
+    let mut buf = Vec::new();
+    if r.take(len).read_to_end(&mut buf)? as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(buf).map_err(|e| invalid(&e.to_string()))
+}
+fn read_opt<R: Read>(r: &mut R) -> io::Result<Option<i64>> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    let value = i64::from_le_bytes(buf);
+    Ok((value != NONE).then_some(value))
+}
+fn read_i32s<R: Read>(r: &mut R, n: usize) -> io::Result<Vec<i32>> {
+    (0..n).map(|_| read_i32(r)).collect()
+}
+fn read_evals<R: Read>(r: &mut R, n: usize) -> io::Result<Vec<Option<Eval>>> {
+    (0..n)
+        .map(|_| {
+            let tag = read_u8(r)?;
+            let value = read_i32(r)?;
+            match tag {
+                0 => Ok(None),
+                1 => Ok(Some(Eval::Centipawns(value))),
+                2 => Ok(Some(Eval::Mate(value))),
+                _ => Err(invalid("unknown evaluation type")),
+            }
+        })
+        .collect()
+}
+fn read_context<R: Read>(r: &mut R) -> io::Result<MoveContext> {
+    let flags = read_u8(r)?;
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    let legal_moves = u16::from_le_bytes(buf);
+    let pieces = read_u8(r)?;
+    Ok(MoveContext {
+        capture: flags & 1 != 0,
+        check: flags & 2 != 0,
+        promotion: flags & 4 != 0,
+        castle: flags & 8 != 0,
+        legal_moves: (legal_moves != 0).then_some(legal_moves),
+        pieces: (pieces != u8::MAX).then_some(pieces),
+    })
+}
+fn read_elos<R: Read>(r: &mut R, n: usize) -> io::Result<Vec<Option<i32>>> {
+    (0..n).map(|_| Ok(read_opt(r)?.map(|v| v as i32))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_dataset, write_dataset, MAGIC, VERSION};
+    use crate::{
+        annotations::Eval,
+        filter::{BothSides, GameFilter, PlayerIs, RatingMode, RatingRange, TimeControlIs},
+        moves::{Color, GameResult, MoveContext, MoveRecord},
+        reader::{BadClocks, GameReader, ReaderConfig},
+    };
+    use std::{io, sync::Arc};
+
+    #[test]
+    fn roundtrip() {
+        let time_control = "180+2".parse().unwrap();
+        let rating = RatingRange {
+            min: Some(1200),
+            max: None,
+            mode: RatingMode::Both,
+        };
+        let config = ReaderConfig {
+            max_games: Some(5),
+            filter: Arc::new(TimeControlIs(time_control).and(rating)),
+            sides: Arc::new(BothSides),
+            bad_clocks: BadClocks::Flag,
+            players: PlayerIs(vec!["someone".to_string()]),
+        };
+        let mut game_reader = GameReader::new(config);
+        game_reader.games_analyzed = 2;
+        game_reader.total_games = 9;
+        game_reader.moves_analyzed = 2;
+        game_reader.bad_clock_games = 1;
+        game_reader.clock_checks = 40;
+        game_reader.clock_mismatches = 3;
+        game_reader.max_allowed_time = 182;
+        for (game_id, color, black_elo, result) in [
+            (3, Color::White, Some(1400), GameResult::WhiteWins),
+            (8, Color::Black, None, GameResult::Unknown),
+        ] {
+            game_reader.moves.push(MoveRecord {
+                game_id,
+                color,
+                clock_before: 120,
+                clock_after: 110,
+                time_spent: 12,
+                increment: 2,
+                white_elo: Some(1300),
+                black_elo,
+                result,
+                time_control: "180+2".parse().unwrap(),
+                eval_before: Some(Eval::Centipawns(-35)),
+                eval: black_elo.map(|_| Eval::Mate(-4)),
+                context: MoveContext {
+                    capture: true,
+                    check: black_elo.is_none(),
+                    legal_moves: black_elo.map(|_| 31),
+                    pieces: black_elo.map(|_| 0),
+                    ..MoveContext::default()
+                },
+                player: black_elo.map(|_| 0),
+                ..MoveRecord::example()
+            });
+        }
+
+        let mut bytes = Vec::new();
+        write_dataset(&game_reader, &mut bytes).unwrap();
+
+        let loaded = read_dataset(&mut &bytes[..]).unwrap();
+        assert_eq!(loaded.moves, game_reader.moves);
+        assert_eq!(loaded.total_games, 9);
+        assert_eq!(loaded.games_analyzed, 2);
+        assert_eq!(loaded.bad_clock_games, 1);
+        assert_eq!(loaded.clock_checks, 40);
+        assert_eq!(loaded.clock_mismatches, 3);
+        assert_eq!(loaded.config.max_games, Some(5));
+        assert_eq!(loaded.config.players.0, vec!["someone"]);
+        assert_eq!(
+            loaded.config.filter.description(),
+            "180+2 seconds, 1200- ELO*"
+        );
+        assert_eq!(loaded.max_allowed_time, 182);
+
+        assert!(read_dataset(&mut &bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn corrupt_string_length() {
+        // a description claiming to be 4 GiB long, in a file that ends right after
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(b"180+2");
+        let error = read_dataset(&mut &bytes[..]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
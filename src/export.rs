@@ -0,0 +1,221 @@
+// Exporting move data
+// writes every recorded move so it can be loaded into a spreadsheet or notebook.
+// rows are written one at a time so this doesn't need a second copy of the data in memory.
+
+use crate::moves::{MoveRecord, MoveStore};
+use clap::ValueEnum;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    /// JSON Lines, one object per move
+    Jsonl,
+}
+
+impl ExportFormat {
+    /// Guess the format from the file extension, defaulting to CSV.
+    pub fn from_path(path: &Path) -> ExportFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl" | "ndjson" | "json") => ExportFormat::Jsonl,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+const COLUMNS: [&str; 19] = [
+    "game_id",
+    "color",
+    "ply",
+    "clock_before",
+    "clock_after",
+    "time_spent",
+    "increment",
+    "white_elo",
+    "black_elo",
+    "result",
+    "time_control",
+    "eval",
+    "centipawn_loss",
+    "capture",
+    "check",
+    "promotion",
+    "castle",
+    "legal_moves",
+    "phase",
+];
+
+/// Write every move to a file. Returns the number of moves written.
+pub fn export_moves(moves: &MoveStore, path: &Path, format: ExportFormat) -> io::Result<usize> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_moves(moves, &mut writer, format)?;
+    writer.flush()?;
+    Ok(moves.len())
+}
+
+pub fn write_moves<W: Write>(
+    moves: &MoveStore,
+    writer: &mut W,
+    format: ExportFormat,
+) -> io::Result<()> {
+    if format == ExportFormat::Csv {
+        writeln!(writer, "{}", COLUMNS.join(","))?;
+    }
+    for record in moves.iter() {
+        match format {
+            ExportFormat::Csv => write_csv(writer, &record)?,
+            ExportFormat::Jsonl => write_json(writer, &record)?,
+        }
+    }
+    Ok(())
+}
+
+// clocks are exported in seconds, keeping the fraction when there is one
+fn seconds(millis: i32) -> f64 {
+    millis as f64 / 1000.0
+}
+
+// none of the values can contain a comma or a quote, so no escaping needed
+fn write_csv<W: Write>(writer: &mut W, r: &MoveRecord) -> io::Result<()> {
+    let opt = |value: Option<i32>| value.map(|v| v.to_string()).unwrap_or_default();
+    writeln!(
+        writer,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        r.game_id,
+        r.color,
+        r.ply,
+        seconds(r.clock_before),
+        seconds(r.clock_after),
+        seconds(r.time_spent),
+        r.increment,
+        opt(r.white_elo),
+        opt(r.black_elo),
+        r.result,
+        r.time_control,
+        r.eval.map(|e| e.to_string()).unwrap_or_default(),
+        opt(r.centipawn_loss()),
+        r.context.capture,
+        r.context.check,
+        r.context.promotion,
+        r.context.castle,
+        opt(r.context.legal_moves.map(i32::from)),
+        r.context.phase().map(|p| p.to_string()).unwrap_or_default()
+    )
+}
+
+fn write_json<W: Write>(writer: &mut W, r: &MoveRecord) -> io::Result<()> {
+    let opt = |value: Option<i32>| value.map(|v| v.to_string()).unwrap_or("null".to_string());
+    write!(
+        writer,
+        "{{\"game_id\":{},\"color\":\"{}\",\"ply\":{},",
+        r.game_id, r.color, r.ply
+    )?;
+    write!(
+        writer,
+        "\"clock_before\":{},\"clock_after\":{},\"time_spent\":{},\"increment\":{},",
+        seconds(r.clock_before),
+        seconds(r.clock_after),
+        seconds(r.time_spent),
+        r.increment
+    )?;
+    write!(
+        writer,
+        "\"white_elo\":{},\"black_elo\":{},\"result\":\"{}\",\"time_control\":\"{}\",",
+        opt(r.white_elo),
+        opt(r.black_elo),
+        r.result,
+        r.time_control
+    )?;
+    // mates aren't numbers, so evals are written the way they appear in the PGN
+    write!(
+        writer,
+        "\"eval\":{},\"centipawn_loss\":{},",
+        r.eval
+            .map(|e| format!("\"{}\"", e))
+            .unwrap_or("null".to_string()),
+        opt(r.centipawn_loss())
+    )?;
+    writeln!(
+        writer,
+        "\"capture\":{},\"check\":{},\"promotion\":{},\"castle\":{},\"legal_moves\":{},\"phase\":{}}}",
+        r.context.capture,
+        r.context.check,
+        r.context.promotion,
+        r.context.castle,
+        opt(r.context.legal_moves.map(i32::from)),
+        r.context
+            .phase()
+            .map(|p| format!("\"{}\"", p))
+            .unwrap_or("null".to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_moves, ExportFormat};
+    use crate::annotations::Eval;
+    use crate::moves::{Color, GameResult, MoveContext, MoveRecord, MoveStore};
+    use std::path::Path;
+
+    fn store() -> MoveStore {
+        let mut store = MoveStore::new();
+        store.push(MoveRecord {
+            game_id: 4,
+            color: Color::Black,
+            ply: 6,
+            clock_before: 58_000,
+            clock_after: 54_700,
+            time_spent: 3_300,
+            white_elo: Some(1500),
+            result: GameResult::Draw,
+            eval_before: Some(Eval::Centipawns(-20)),
+            eval: Some(Eval::Centipawns(150)),
+            context: MoveContext {
+                capture: true,
+                check: true,
+                legal_moves: Some(27),
+                pieces: Some(9),
+                ..MoveContext::default()
+            },
+            ..MoveRecord::example()
+        });
+        store
+    }
+
+    #[test]
+    fn csv() {
+        let mut out = Vec::new();
+        write_moves(&store(), &mut out, ExportFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "game_id,color,ply,clock_before,clock_after,time_spent,increment,white_elo,black_elo,result,time_control,eval,centipawn_loss,capture,check,promotion,castle,legal_moves,phase\n\
+             4,black,6,58,54.7,3.3,0,1500,,1/2-1/2,60+0,1.50,170,true,true,false,false,27,middlegame\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let mut out = Vec::new();
+        write_moves(&store(), &mut out, ExportFormat::Jsonl).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"game_id\":4,\"color\":\"black\",\"ply\":6,\"clock_before\":58,\"clock_after\":54.7,\"time_spent\":3.3,\"increment\":0,\"white_elo\":1500,\"black_elo\":null,\"result\":\"1/2-1/2\",\"time_control\":\"60+0\",\"eval\":\"1.50\",\"centipawn_loss\":170,\"capture\":true,\"check\":true,\"promotion\":false,\"castle\":false,\"legal_moves\":27,\"phase\":\"middlegame\"}\n"
+        );
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(
+            ExportFormat::from_path(Path::new("moves.jsonl")),
+            ExportFormat::Jsonl
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("moves.csv")),
+            ExportFormat::Csv
+        );
+    }
+}
@@ -0,0 +1,904 @@
+// Game filters
+// decide from a game's headers whether we should read it at all.
+// each rule is its own GameFilter, and rules can be combined with and/or/not,
+// so adding a new one doesn't mean touching the reader.
+
+use crate::moves::{Color, Speed, TimeControl};
+use clap::ValueEnum;
+use std::{fmt, sync::Arc};
+
+/// The headers of a single game, e.g. `("WhiteElo", "1500")`.
+/// The strings are reused between games so collecting them doesn't allocate for every game.
+#[derive(Debug, Clone, Default)]
+pub struct GameHeaders {
+    entries: Vec<(String, String)>,
+    len: usize,
+}
+
+impl GameHeaders {
+    pub fn new() -> GameHeaders {
+        GameHeaders::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn push(&mut self, key: &str, value: &str) {
+        if let Some((k, v)) = self.entries.get_mut(self.len) {
+            k.clear();
+            k.push_str(key);
+            v.clear();
+            v.push_str(value);
+        } else {
+            self.entries.push((key.to_string(), value.to_string()));
+        }
+        self.len += 1;
+    }
+
+    /// Value of the first header called `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries[..self.len]
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// `None` for games without a clock (`0+0`), their moves can't be measured against one.
+    pub fn time_control(&self) -> Option<TimeControl> {
+        let time_control: TimeControl = self.get("TimeControl")?.parse().ok()?;
+        (time_control.starting_clock() > 0).then_some(time_control)
+    }
+
+    /// Ratings are `None` when missing or unknown (lichess writes "?").
+    pub fn white_elo(&self) -> Option<i32> {
+        self.get("WhiteElo")?.parse().ok()
+    }
+
+    pub fn black_elo(&self) -> Option<i32> {
+        self.get("BlackElo")?.parse().ok()
+    }
+
+    /// Name of the player with this color.
+    pub fn player(&self, color: Color) -> Option<&str> {
+        self.get(match color {
+            Color::White => "White",
+            Color::Black => "Black",
+        })
+    }
+
+    /// When the game was played, preferring `UTCDate` (lichess) over `Date`.
+    /// `None` when missing or partly unknown, like `2023.??.??`.
+    pub fn date(&self) -> Option<Date> {
+        Date::from_header(self.get("UTCDate").or(self.get("Date"))?)
+    }
+
+    /// Whether the game was rated, going by lichess' `Event` ("Rated Blitz game" or "Casual Blitz game").
+    pub fn rated(&self) -> Option<bool> {
+        let event = self.get("Event")?;
+        if event.starts_with("Rated") {
+            Some(true)
+        } else if event.starts_with("Casual") {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Where the game was paired, going by lichess' `Event`. Anything that isn't an arena or swiss is a game.
+    pub fn event_kind(&self) -> Option<EventKind> {
+        let event = self.get("Event")?;
+        Some(if event.contains(" swiss") {
+            EventKind::Swiss
+        } else if event.contains(" tournament") {
+            EventKind::Tournament
+        } else {
+            EventKind::Game
+        })
+    }
+
+    /// The `Variant` header, standard chess when there isn't one.
+    pub fn variant(&self) -> &str {
+        self.get("Variant").unwrap_or("Standard")
+    }
+
+    pub fn termination(&self) -> Option<Termination> {
+        Termination::from_header(self.get("Termination")?)
+    }
+}
+
+/// A calendar date, as written in PGN headers (`2023.10.31`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// `None` if there's no such day, like February 30th.
+    fn new(year: u16, month: u8, day: u8) -> Option<Date> {
+        (1..=days_in_month(year, month))
+            .contains(&day)
+            .then_some(Date { year, month, day })
+    }
+
+    fn from_header(value: &str) -> Option<Date> {
+        let mut parts = value.split('.');
+        let (Some(year), Some(month), Some(day), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return None;
+        };
+        Date::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+    }
+
+    /// Parse a date given on the command line, `2023-10-31` or `2023.10.31`.
+    /// The month and day can be left out (`2023-10`, `2023`), in which case this is the first day
+    /// of that month or year, or the last one if `end` is set.
+    pub fn parse_bound(value: &str, end: bool) -> Result<Date, Box<dyn std::error::Error>> {
+        let invalid = || format!("Invalid date: {}", value);
+        let parts = value
+            .split(['-', '.'])
+            .map(|part| part.parse::<u16>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (year, month, day) = match parts[..] {
+            [year] => (year, if end { 12 } else { 1 }, None),
+            [year, month] => (year, month, None),
+            [year, month, day] => (year, month, Some(day)),
+            _ => return Err(invalid().into()),
+        };
+        let month = u8::try_from(month).map_err(|_| invalid())?;
+        let day = match day {
+            Some(day) => u8::try_from(day).map_err(|_| invalid())?,
+            None if end => days_in_month(year, month),
+            None => 1,
+        };
+        Ok(Date::new(year, month, day).ok_or_else(invalid)?)
+    }
+}
+
+/// 0 for a month that doesn't exist.
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// How a game was paired on lichess.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventKind {
+    /// Lobby and quick pairing games
+    Game,
+    /// Arena tournaments
+    Tournament,
+    Swiss,
+}
+
+/// Why a game ended, from the `Termination` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Termination {
+    /// Checkmate, resignation, draws
+    Normal,
+    TimeForfeit,
+    Abandoned,
+    RulesInfraction,
+    Unterminated,
+}
+
+impl Termination {
+    pub fn from_header(value: &str) -> Option<Termination> {
+        match value {
+            "Normal" => Some(Termination::Normal),
+            "Time forfeit" => Some(Termination::TimeForfeit),
+            "Abandoned" => Some(Termination::Abandoned),
+            "Rules infraction" => Some(Termination::RulesInfraction),
+            "Unterminated" => Some(Termination::Unterminated),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Termination::Normal => write!(f, "normal"),
+            Termination::TimeForfeit => write!(f, "time forfeit"),
+            Termination::Abandoned => write!(f, "abandoned"),
+            Termination::RulesInfraction => write!(f, "rules infraction"),
+            Termination::Unterminated => write!(f, "unterminated"),
+        }
+    }
+}
+
+/// A rule deciding which games get read.
+/// Closures taking `&GameHeaders` and returning a bool work as filters too.
+pub trait GameFilter: Send + Sync {
+    fn accept(&self, headers: &GameHeaders) -> bool;
+
+    /// Short human readable description, used in graph captions.
+    fn description(&self) -> String {
+        "Custom filter".to_string()
+    }
+
+    fn and<F: GameFilter + 'static>(self, other: F) -> And
+    where
+        Self: Sized + 'static,
+    {
+        And(vec![Arc::new(self), Arc::new(other)])
+    }
+
+    fn or<F: GameFilter + 'static>(self, other: F) -> Or
+    where
+        Self: Sized + 'static,
+    {
+        Or(vec![Arc::new(self), Arc::new(other)])
+    }
+
+    fn not(self) -> Not
+    where
+        Self: Sized + 'static,
+    {
+        Not(Arc::new(self))
+    }
+}
+
+impl<F: Fn(&GameHeaders) -> bool + Send + Sync> GameFilter for F {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        self(headers)
+    }
+}
+
+/// Accepts every game.
+#[derive(Debug, Clone, Copy)]
+pub struct AcceptAll;
+
+impl GameFilter for AcceptAll {
+    fn accept(&self, _: &GameHeaders) -> bool {
+        true
+    }
+    fn description(&self) -> String {
+        "All games".to_string()
+    }
+}
+
+/// Accepts games only if every inner filter does.
+#[derive(Clone)]
+pub struct And(pub Vec<Arc<dyn GameFilter>>);
+
+impl GameFilter for And {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        self.0.iter().all(|f| f.accept(headers))
+    }
+    fn description(&self) -> String {
+        self.0
+            .iter()
+            .map(|f| f.description())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Accepts games if any inner filter does.
+#[derive(Clone)]
+pub struct Or(pub Vec<Arc<dyn GameFilter>>);
+
+impl GameFilter for Or {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        self.0.iter().any(|f| f.accept(headers))
+    }
+    fn description(&self) -> String {
+        format!(
+            "({})",
+            self.0
+                .iter()
+                .map(|f| f.description())
+                .collect::<Vec<_>>()
+                .join(" or ")
+        )
+    }
+}
+
+/// Accepts the games the inner filter rejects.
+#[derive(Clone)]
+pub struct Not(pub Arc<dyn GameFilter>);
+
+impl GameFilter for Not {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        !self.0.accept(headers)
+    }
+    fn description(&self) -> String {
+        format!("not {}", self.0.description())
+    }
+}
+
+/// Games played with exactly this time control.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeControlIs(pub TimeControl);
+
+impl GameFilter for TimeControlIs {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        headers.time_control() == Some(self.0)
+    }
+    fn description(&self) -> String {
+        format!("{} seconds", self.0)
+    }
+}
+
+/// Games whose base time and increment are both between those of `min` and `max` (both ends included).
+#[derive(Debug, Clone, Copy)]
+pub struct TimeControlRange {
+    pub min: TimeControl,
+    pub max: TimeControl,
+}
+
+impl GameFilter for TimeControlRange {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        headers.time_control().is_some_and(|tc| {
+            (self.min.base..=self.max.base).contains(&tc.base)
+                && (self.min.increment..=self.max.increment).contains(&tc.increment)
+        })
+    }
+    fn description(&self) -> String {
+        format!("{} to {} seconds", self.min, self.max)
+    }
+}
+
+/// Games in a lichess speed category, e.g. blitz.
+#[derive(Debug, Clone, Copy)]
+pub struct SpeedIs(pub Speed);
+
+impl GameFilter for SpeedIs {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        headers
+            .time_control()
+            .is_some_and(|tc| tc.speed() == self.0)
+    }
+    fn description(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Parse a comma separated list of time controls (`600+0`), ranges (`180+0..300+5`)
+/// and speed categories (`blitz`) into one filter accepting any of them.
+pub fn time_control_filter(spec: &str) -> Result<Arc<dyn GameFilter>, Box<dyn std::error::Error>> {
+    let mut filters: Vec<Arc<dyn GameFilter>> = Vec::new();
+    for item in spec.split(',').map(str::trim) {
+        if let Some((min, max)) = item.split_once("..") {
+            filters.push(Arc::new(TimeControlRange {
+                min: min.parse()?,
+                max: max.parse()?,
+            }));
+        } else if item.contains('+') {
+            filters.push(Arc::new(TimeControlIs(item.parse()?)));
+        } else {
+            filters.push(Arc::new(SpeedIs(item.parse()?)));
+        }
+    }
+    if filters.len() == 1 {
+        Ok(filters.remove(0))
+    } else {
+        Ok(Arc::new(Or(filters)))
+    }
+}
+
+/// Which rating(s) a RatingRange looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum RatingMode {
+    /// The average of both ratings
+    Average,
+    /// Both players individually
+    #[default]
+    Both,
+    /// At least one of the players
+    Either,
+}
+
+/// Games where the ratings are inside the range (both ends included).
+/// Games with an unknown rating are rejected unless the range is unbounded.
+#[derive(Debug, Clone, Copy)]
+pub struct RatingRange {
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+    pub mode: RatingMode,
+}
+
+impl RatingRange {
+    pub fn contains(&self, elo: i32) -> bool {
+        !(self.min.is_some_and(|min| elo < min) || self.max.is_some_and(|max| elo > max))
+    }
+
+    fn is_unbounded(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+}
+
+impl GameFilter for RatingRange {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        if self.is_unbounded() {
+            return true;
+        }
+        let (Some(white), Some(black)) = (headers.white_elo(), headers.black_elo()) else {
+            return false;
+        };
+        match self.mode {
+            // rounded down, same as lichess does it
+            RatingMode::Average => self.contains((white + black) / 2),
+            RatingMode::Both => self.contains(white) && self.contains(black),
+            RatingMode::Either => self.contains(white) || self.contains(black),
+        }
+    }
+    fn description(&self) -> String {
+        if self.is_unbounded() {
+            return "No ELO Limit".to_string();
+        }
+        let mut str = String::new();
+        match self.mode {
+            RatingMode::Average => str.push_str("Avg "),
+            RatingMode::Both => {}
+            RatingMode::Either => str.push_str("Either "),
+        }
+        if let Some(rating) = self.min {
+            str.push_str(&rating.to_string());
+        }
+        str.push('-');
+        if let Some(rating) = self.max {
+            str.push_str(&rating.to_string());
+        };
+        str.push_str(" ELO*");
+        str
+    }
+}
+
+/// Keeps the moves of whichever player is inside the range, whatever the mode.
+impl SideFilter for RatingRange {
+    fn keep(&self, headers: &GameHeaders, color: Color) -> bool {
+        let elo = match color {
+            Color::White => headers.white_elo(),
+            Color::Black => headers.black_elo(),
+        };
+        self.is_unbounded() || elo.is_some_and(|elo| self.contains(elo))
+    }
+}
+
+/// Games where the absolute difference between the two ratings is inside the range.
+#[derive(Debug, Clone, Copy)]
+pub struct RatingGap {
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+}
+
+impl GameFilter for RatingGap {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        let (Some(white), Some(black)) = (headers.white_elo(), headers.black_elo()) else {
+            return false;
+        };
+        let gap = (white - black).abs();
+        !(self.min.is_some_and(|min| gap < min) || self.max.is_some_and(|max| gap > max))
+    }
+    fn description(&self) -> String {
+        format!(
+            "ELO gap {}-{}",
+            self.min.unwrap_or(0),
+            self.max.map(|m| m.to_string()).unwrap_or_default()
+        )
+    }
+}
+
+/// Games played by any of these players, with names compared ignoring case like lichess usernames.
+/// As a side filter, keeps only their moves.
+#[derive(Debug, Clone)]
+pub struct PlayerIs(pub Vec<String>);
+
+impl PlayerIs {
+    /// Where `name` is in the list.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.0.iter().position(|p| p.eq_ignore_ascii_case(name))
+    }
+}
+
+impl GameFilter for PlayerIs {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        [Color::White, Color::Black]
+            .iter()
+            .any(|&color| self.keep(headers, color))
+    }
+    fn description(&self) -> String {
+        self.0.join("/")
+    }
+}
+
+impl SideFilter for PlayerIs {
+    fn keep(&self, headers: &GameHeaders, color: Color) -> bool {
+        headers
+            .player(color)
+            .is_some_and(|name| self.position(name).is_some())
+    }
+}
+
+/// Games played between two dates (both included). Games without a known date are rejected.
+#[derive(Debug, Clone, Copy)]
+pub struct DateRange {
+    pub from: Option<Date>,
+    pub to: Option<Date>,
+}
+
+impl GameFilter for DateRange {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        headers.date().is_some_and(|date| {
+            !(self.from.is_some_and(|from| date < from) || self.to.is_some_and(|to| date > to))
+        })
+    }
+    fn description(&self) -> String {
+        format!(
+            "{} to {}",
+            self.from.map(|d| d.to_string()).unwrap_or_default(),
+            self.to.map(|d| d.to_string()).unwrap_or_default()
+        )
+    }
+}
+
+/// Rated games only, or casual games only.
+#[derive(Debug, Clone, Copy)]
+pub struct RatedIs(pub bool);
+
+impl GameFilter for RatedIs {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        headers.rated() == Some(self.0)
+    }
+    fn description(&self) -> String {
+        if self.0 { "Rated" } else { "Casual" }.to_string()
+    }
+}
+
+/// Games paired in any of these ways.
+#[derive(Debug, Clone)]
+pub struct EventKindIs(pub Vec<EventKind>);
+
+impl GameFilter for EventKindIs {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        headers
+            .event_kind()
+            .is_some_and(|kind| self.0.contains(&kind))
+    }
+    fn description(&self) -> String {
+        self.0
+            .iter()
+            .map(|kind| match kind {
+                EventKind::Game => "Pool",
+                EventKind::Tournament => "Arena",
+                EventKind::Swiss => "Swiss",
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// Games of any of these variants, compared ignoring case (`standard`, `chess960`, ...).
+#[derive(Debug, Clone)]
+pub struct VariantIs(pub Vec<String>);
+
+impl GameFilter for VariantIs {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        let variant = headers.variant();
+        self.0.iter().any(|v| v.eq_ignore_ascii_case(variant))
+    }
+    fn description(&self) -> String {
+        self.0.join("/")
+    }
+}
+
+/// Rejects games that ended in one of these ways. Games without a `Termination` header are kept.
+#[derive(Debug, Clone)]
+pub struct ExcludeTerminations(pub Vec<Termination>);
+
+impl GameFilter for ExcludeTerminations {
+    fn accept(&self, headers: &GameHeaders) -> bool {
+        !headers
+            .termination()
+            .is_some_and(|termination| self.0.contains(&termination))
+    }
+    fn description(&self) -> String {
+        format!(
+            "no {}",
+            self.0
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join("/")
+        )
+    }
+}
+
+/// Decides whose moves get recorded in a game that was accepted.
+/// Closures taking `&GameHeaders` and a `Color` work too.
+pub trait SideFilter: Send + Sync {
+    fn keep(&self, headers: &GameHeaders, color: Color) -> bool;
+}
+
+impl<F: Fn(&GameHeaders, Color) -> bool + Send + Sync> SideFilter for F {
+    fn keep(&self, headers: &GameHeaders, color: Color) -> bool {
+        self(headers, color)
+    }
+}
+
+/// Records the moves of both players.
+#[derive(Debug, Clone, Copy)]
+pub struct BothSides;
+
+impl SideFilter for BothSides {
+    fn keep(&self, _: &GameHeaders, _: Color) -> bool {
+        true
+    }
+}
+
+/// Stands in for the filters a saved dataset was collected with.
+/// Only the description survives saving, so this accepts everything.
+#[derive(Debug, Clone)]
+pub struct Described(pub String);
+
+impl GameFilter for Described {
+    fn accept(&self, _: &GameHeaders) -> bool {
+        true
+    }
+    fn description(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        time_control_filter, AcceptAll, Date, DateRange, EventKind, EventKindIs,
+        ExcludeTerminations, GameFilter, GameHeaders, PlayerIs, RatedIs, RatingGap, RatingMode,
+        RatingRange, SideFilter, Termination, TimeControlIs, VariantIs,
+    };
+    use crate::moves::Color;
+
+    fn headers(time_control: &str, white_elo: &str, black_elo: &str) -> GameHeaders {
+        let mut headers = GameHeaders::new();
+        headers.push("Event", "Rated Blitz game");
+        headers.push("WhiteElo", white_elo);
+        headers.push("BlackElo", black_elo);
+        headers.push("TimeControl", time_control);
+        headers
+    }
+
+    #[test]
+    fn reuses_entries() {
+        let mut h = headers("600+0", "1500", "1600");
+        h.clear();
+        assert_eq!(h.get("Event"), None);
+        h.push("WhiteElo", "?");
+        assert_eq!(h.get("WhiteElo"), Some("?"));
+        assert_eq!(h.white_elo(), None);
+        assert_eq!(h.get("BlackElo"), None);
+    }
+
+    #[test]
+    fn rules() {
+        let blitz = TimeControlIs("180+2".parse().unwrap());
+        assert!(blitz.accept(&headers("180+2", "1500", "1600")));
+        assert!(!blitz.accept(&headers("180+0", "1500", "1600")));
+
+        let rating = RatingRange {
+            min: Some(1000),
+            max: Some(1550),
+            mode: RatingMode::Both,
+        };
+        assert!(rating.accept(&headers("180+2", "1500", "1000")));
+        assert!(!rating.accept(&headers("180+2", "1500", "1600")));
+        assert!(!rating.accept(&headers("180+2", "1500", "?")));
+        assert_eq!(rating.description(), "1000-1550 ELO*");
+
+        let unbounded = RatingRange {
+            min: None,
+            max: None,
+            mode: RatingMode::Both,
+        };
+        assert!(unbounded.accept(&headers("180+2", "?", "?")));
+    }
+
+    #[test]
+    fn rating_modes() {
+        let lopsided = headers("180+2", "1200", "1900");
+        let mut rating = RatingRange {
+            min: Some(1500),
+            max: Some(1600),
+            mode: RatingMode::Average,
+        };
+        assert!(rating.accept(&lopsided));
+        rating.mode = RatingMode::Both;
+        assert!(!rating.accept(&lopsided));
+        rating.mode = RatingMode::Either;
+        assert!(!rating.accept(&lopsided));
+
+        rating.max = Some(2000);
+        assert!(rating.accept(&lopsided));
+        assert_eq!(rating.description(), "Either 1500-2000 ELO*");
+        // only black is inside the range
+        assert!(!rating.keep(&lopsided, Color::White));
+        assert!(rating.keep(&lopsided, Color::Black));
+    }
+
+    #[test]
+    fn time_control_specs() {
+        let accepts = |spec: &str, tc: &str| {
+            time_control_filter(spec)
+                .unwrap()
+                .accept(&headers(tc, "1500", "1500"))
+        };
+        assert!(accepts("600+0", "600+0"));
+        assert!(!accepts("600+0", "600+5"));
+        assert!(accepts("180+0,180+2", "180+2"));
+        assert!(accepts("180+0..300+5", "300+3"));
+        assert!(!accepts("180+0..300+5", "600+0"));
+        assert!(accepts("blitz", "180+2"));
+        assert!(!accepts("blitz", "60+0"));
+        assert!(accepts("bullet, 600+0", "600+0"));
+        assert!(!accepts("blitz", "-"));
+
+        assert_eq!(
+            time_control_filter("blitz,600+0").unwrap().description(),
+            "(Blitz or 600+0 seconds)"
+        );
+        assert!(time_control_filter("fast").is_err());
+        assert!(time_control_filter("180+0..").is_err());
+    }
+
+    #[test]
+    fn rating_gap() {
+        let gap = RatingGap {
+            min: None,
+            max: Some(200),
+        };
+        assert!(gap.accept(&headers("180+2", "1500", "1700")));
+        assert!(gap.accept(&headers("180+2", "1700", "1500")));
+        assert!(!gap.accept(&headers("180+2", "1500", "1701")));
+        assert!(!gap.accept(&headers("180+2", "1500", "?")));
+        assert_eq!(gap.description(), "ELO gap 0-200");
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(
+            Date::parse_bound("2023-10", false).unwrap().to_string(),
+            "2023-10-01"
+        );
+        assert_eq!(
+            Date::parse_bound("2023", true).unwrap().to_string(),
+            "2023-12-31"
+        );
+        assert_eq!(
+            Date::parse_bound("2024-02", true).unwrap().to_string(),
+            "2024-02-29"
+        );
+        assert_eq!(
+            Date::parse_bound("2023-02", true).unwrap().to_string(),
+            "2023-02-28"
+        );
+        assert!(Date::parse_bound("2024-02-29", false).is_ok());
+        for date in [
+            "2023-13",
+            "2023-02-29",
+            "2024-02-30",
+            "2023-04-31",
+            "1900-02-29",
+        ] {
+            assert!(Date::parse_bound(date, false).is_err(), "{}", date);
+        }
+        assert!(Date::parse_bound("october", false).is_err());
+
+        let october = DateRange {
+            from: Some(Date::parse_bound("2023.10", false).unwrap()),
+            to: Some(Date::parse_bound("2023.10", true).unwrap()),
+        };
+        let mut h = headers("180+2", "1500", "1500");
+        h.push("Date", "2023.11.01");
+        assert!(!october.accept(&h));
+        // UTCDate wins
+        h.push("UTCDate", "2023.10.31");
+        assert!(october.accept(&h));
+        h.clear();
+        h.push("Date", "2023.??.??");
+        assert!(!october.accept(&h));
+        // 266 would wrap around to 10 as a u8
+        h.clear();
+        h.push("Date", "2023.266.01");
+        assert!(!october.accept(&h));
+        assert!(Date::from_header("2023.10.32").is_none());
+        assert!(Date::from_header("2023.00.01").is_none());
+        assert!(Date::from_header("2024.02.29").is_some());
+        assert!(Date::from_header("2000.02.29").is_some());
+        assert!(Date::from_header("2023.02.29").is_none());
+        assert!(Date::from_header("2024.02.30").is_none());
+        assert!(Date::from_header("2023.04.31").is_none());
+        assert_eq!(
+            Date::from_header("2023.10.31").unwrap().to_string(),
+            "2023-10-31"
+        );
+        assert_eq!(october.description(), "2023-10-01 to 2023-10-31");
+    }
+
+    #[test]
+    fn event_variant_termination() {
+        let mut h = headers("180+2", "1500", "1500");
+        h.push("Variant", "Chess960");
+        h.push("Termination", "Abandoned");
+
+        // the headers() helper makes rated pool games
+        assert!(RatedIs(true).accept(&h));
+        assert!(!RatedIs(true).accept(&GameHeaders::new()));
+        assert!(EventKindIs(vec![EventKind::Game]).accept(&h));
+        // only the first Event counts, so this needs its own headers
+        let mut casual = GameHeaders::new();
+        casual.push(
+            "Event",
+            "Casual Blitz tournament https://lichess.org/tournament/abc",
+        );
+        assert!(RatedIs(false).accept(&casual));
+        assert!(EventKindIs(vec![EventKind::Tournament, EventKind::Swiss]).accept(&casual));
+        assert!(!EventKindIs(vec![EventKind::Game]).accept(&casual));
+
+        let standard = VariantIs(vec!["standard".to_string()]);
+        assert!(!standard.accept(&h));
+        assert!(standard.accept(&casual));
+
+        let clean = ExcludeTerminations(vec![Termination::Abandoned, Termination::RulesInfraction]);
+        assert!(!clean.accept(&h));
+        assert!(clean.accept(&casual));
+        assert_eq!(clean.description(), "no abandoned/rules infraction");
+    }
+
+    #[test]
+    fn players() {
+        let mut h = headers("180+2", "1500", "1500");
+        h.push("White", "DrNykterstein");
+        h.push("Black", "someone");
+        let players = PlayerIs(vec!["nobody".to_string(), "drnykterstein".to_string()]);
+        assert!(players.accept(&h));
+        assert!(players.keep(&h, Color::White));
+        assert!(!players.keep(&h, Color::Black));
+        assert_eq!(players.position("DRNYKTERSTEIN"), Some(1));
+        assert!(!PlayerIs(vec!["nobody".to_string()]).accept(&h));
+    }
+
+    #[test]
+    fn combinators() {
+        let blitz = TimeControlIs("180+2".parse().unwrap());
+        let rating = RatingRange {
+            min: None,
+            max: Some(1550),
+            mode: RatingMode::Both,
+        };
+        let filter = blitz.and(rating);
+        assert!(filter.accept(&headers("180+2", "1500", "1500")));
+        assert!(!filter.accept(&headers("180+2", "1500", "1600")));
+        assert!(!filter.accept(&headers("60+0", "1500", "1500")));
+        assert_eq!(filter.description(), "180+2 seconds, -1550 ELO*");
+
+        let either = blitz.or(rating);
+        assert!(either.accept(&headers("60+0", "1500", "1500")));
+        assert!(!either.accept(&headers("60+0", "1500", "1600")));
+
+        let not_blitz = blitz.not();
+        assert!(not_blitz.accept(&headers("60+0", "1500", "1500")));
+
+        // closures work as filters
+        let rated = |h: &GameHeaders| h.get("Event").is_some_and(|e| e.starts_with("Rated"));
+        assert!(AcceptAll
+            .and(rated)
+            .accept(&headers("60+0", "1500", "1600")));
+    }
+}
@@ -0,0 +1,355 @@
+// Inference for a fitted regression model
+// standard errors and t tests for the coefficients, and confidence and prediction bands around the
+// curve. everything is worked out on the least squares problem the model was fitted as
+// (see RegressionModel::terms), so for the exp and power curves it's about ln(y) and the bands get
+// turned back into seconds at the end.
+
+use crate::regression::RegressionModel;
+use nalgebra::{DMatrix, SVD};
+use std::error::Error;
+
+/// Standard error and t test of one fitted coefficient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoefficientStats {
+    /// Name of the term the coefficient goes with, e.g. `x^2`.
+    pub term: String,
+    pub estimate: f64,
+    pub standard_error: f64,
+    pub t: f64,
+    /// Two-sided p-value for the coefficient being 0.
+    pub p: f64,
+}
+
+/// Everything needed for intervals around a fitted model.
+#[derive(Debug, Clone)]
+pub struct Inference {
+    pub coefficients: Vec<CoefficientStats>,
+    /// Residual standard error, in the space the model was fitted in.
+    pub sigma: f64,
+    pub degrees_of_freedom: f64,
+    /// Confidence level of the bands, e.g. 0.95.
+    pub confidence: f64,
+    t_critical: f64,
+    // (X^T X)^-1, the coefficient covariance without the sigma^2
+    covariance: DMatrix<f64>,
+}
+
+impl Inference {
+    /// Work out the standard errors of `model` fitted to the points, with bands at the `confidence` level.
+    pub fn of(
+        model: &dyn RegressionModel,
+        x_values: &[f64],
+        y_values: &[f64],
+        confidence: f64,
+    ) -> Result<Inference, Box<dyn Error>> {
+        if !(0.0..1.0).contains(&confidence) {
+            return Err(format!("Invalid confidence level: {}", confidence).into());
+        }
+        // only the points the model could be fitted on
+        let (rows, y_values): (Vec<Vec<f64>>, Vec<f64>) = x_values
+            .iter()
+            .zip(y_values)
+            .map(|(&x, &y)| (model.terms(x), model.link(y)))
+            .filter(|(terms, y)| y.is_finite() && terms.iter().all(|t| t.is_finite()))
+            .unzip();
+        let estimates = model.linear_coefficients();
+        let (n, p) = (rows.len(), estimates.len());
+        if n <= p {
+            return Err(format!(
+                "Not enough points for standard errors ({} points for {} coefficients)",
+                n, p
+            )
+            .into());
+        }
+
+        // scale every column to length 1 first, x^5 and 1 are too far apart otherwise
+        let design = DMatrix::from_fn(n, p, |i, j| rows[i][j]);
+        let norms: Vec<f64> = design
+            .column_iter()
+            .map(|column| column.norm().max(f64::MIN_POSITIVE))
+            .collect();
+        let scaled = DMatrix::from_fn(n, p, |i, j| design[(i, j)] / norms[j]);
+        let svd = SVD::new(scaled, false, true);
+        let v_t = svd.v_t.ok_or("Couldn't decompose the design matrix")?;
+        // (X^T X)^-1 = V S^-2 V^T
+        let inverse_squares = DMatrix::from_diagonal(&svd.singular_values.map(|s| {
+            if s > 1e-10 {
+                1.0 / (s * s)
+            } else {
+                0.0
+            }
+        }));
+        let scaled_covariance = v_t.transpose() * inverse_squares * &v_t;
+        let covariance = DMatrix::from_fn(p, p, |i, j| {
+            scaled_covariance[(i, j)] / (norms[i] * norms[j])
+        });
+
+        let sse: f64 = rows
+            .iter()
+            .zip(&y_values)
+            .map(|(terms, y)| (y - dot(terms, &estimates)).powi(2))
+            .sum();
+        let degrees_of_freedom = (n - p) as f64;
+        let sigma = (sse / degrees_of_freedom).sqrt();
+
+        let coefficients = model
+            .term_names()
+            .into_iter()
+            .zip(&estimates)
+            .enumerate()
+            .map(|(i, (term, &estimate))| {
+                let standard_error = sigma * covariance[(i, i)].sqrt();
+                let t = estimate / standard_error;
+                CoefficientStats {
+                    term,
+                    estimate,
+                    standard_error,
+                    t,
+                    p: t_test(t, degrees_of_freedom),
+                }
+            })
+            .collect();
+
+        Ok(Inference {
+            coefficients,
+            sigma,
+            degrees_of_freedom,
+            confidence,
+            t_critical: t_critical(confidence, degrees_of_freedom),
+            covariance,
+        })
+    }
+
+    /// Confidence interval of a coefficient.
+    pub fn interval(&self, coefficient: &CoefficientStats) -> (f64, f64) {
+        let half_width = self.t_critical * coefficient.standard_error;
+        (
+            coefficient.estimate - half_width,
+            coefficient.estimate + half_width,
+        )
+    }
+
+    /// Lower and upper bound for the average time taken at `x`.
+    pub fn confidence_band(&self, model: &dyn RegressionModel, x: f64) -> (f64, f64) {
+        self.band(model, x, 0.0)
+    }
+
+    /// Lower and upper bound for the time taken on a single move at `x`.
+    pub fn prediction_band(&self, model: &dyn RegressionModel, x: f64) -> (f64, f64) {
+        self.band(model, x, 1.0)
+    }
+
+    // a prediction band is a confidence band plus the scatter of a single point around the curve
+    fn band(&self, model: &dyn RegressionModel, x: f64, scatter: f64) -> (f64, f64) {
+        let terms = model.terms(x);
+        let fitted = dot(&terms, &model.linear_coefficients());
+        let mut leverage = 0.0;
+        for (i, a) in terms.iter().enumerate() {
+            for (j, b) in terms.iter().enumerate() {
+                leverage += a * self.covariance[(i, j)] * b;
+            }
+        }
+        let half_width = self.t_critical * self.sigma * (scatter + leverage).sqrt();
+        (
+            model.unlink(fitted - half_width),
+            model.unlink(fitted + half_width),
+        )
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Two-sided p-value of a t statistic with `degrees_of_freedom`.
+pub fn t_test(t: f64, degrees_of_freedom: f64) -> f64 {
+    if t.is_nan() {
+        return f64::NAN;
+    }
+    incomplete_beta(
+        degrees_of_freedom / 2.0,
+        0.5,
+        degrees_of_freedom / (degrees_of_freedom + t * t),
+    )
+}
+
+/// The t value that a `confidence` share of the t distribution falls within, e.g. 1.96ish for 0.95.
+pub fn t_critical(confidence: f64, degrees_of_freedom: f64) -> f64 {
+    let alpha = 1.0 - confidence;
+    let mut high = 1.0;
+    while t_test(high, degrees_of_freedom) > alpha {
+        high *= 2.0;
+    }
+    // the p-value only goes down as t goes up, so just bisect
+    let mut low = 0.0;
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if t_test(mid, degrees_of_freedom) > alpha {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// regularized incomplete beta function I_x(a, b), numerical recipes style
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // the continued fraction converges fast on this side, use the symmetry on the other
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut result = d;
+    // lots of degrees of freedom need a lot of iterations, a million moves takes about a thousand
+    for m in 1..100_000 {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        for step in [even, odd] {
+            d = 1.0 + step * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + step / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            result *= d * c;
+        }
+        if (d * c - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    result
+}
+
+// lanczos approximation of ln(gamma(x))
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // reflection formula
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + G + 0.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{t_critical, t_test, Inference};
+    use crate::analysis::to_precision;
+    use crate::regression::ModelKind;
+
+    #[test]
+    fn t_distribution() {
+        assert_eq!(to_precision(t_test(2.0, 10.0), 4), 0.0734);
+        assert_eq!(to_precision(t_test(0.0, 10.0), 4), 1.0);
+        assert_eq!(to_precision(t_critical(0.95, 10.0), 4), 2.2281);
+        assert_eq!(to_precision(t_critical(0.99, 3.0), 4), 5.8409);
+        // close enough to the normal distribution with a lot of moves
+        assert_eq!(to_precision(t_critical(0.95, 1_000_000.0), 3), 1.96);
+    }
+
+    #[test]
+    fn line_inference() {
+        let x_values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let y_values = [2.0, 4.0, 5.0, 4.0, 5.0];
+        let model = ModelKind::Polynomial(1).fit(&x_values, &y_values).unwrap();
+        let inference = Inference::of(model.as_ref(), &x_values, &y_values, 0.95).unwrap();
+        let slope = &inference.coefficients[0];
+        let intercept = &inference.coefficients[1];
+        assert_eq!(slope.term, "x");
+        assert_eq!(to_precision(slope.estimate, 4), 0.6);
+        assert_eq!(to_precision(slope.standard_error, 4), 0.2828);
+        assert_eq!(to_precision(slope.t, 4), 2.1213);
+        assert_eq!(to_precision(slope.p, 3), 0.124);
+        assert_eq!(to_precision(intercept.standard_error, 4), 0.9381);
+        assert_eq!(inference.degrees_of_freedom, 3.0);
+        // 0.6 +- 3.1824 * 0.2828
+        let (low, high) = inference.interval(slope);
+        assert_eq!(
+            (to_precision(low, 4), to_precision(high, 4)),
+            (-0.3001, 1.5001)
+        );
+
+        // 4 +- t * sqrt(0.8 / 5) and 4 +- t * sqrt(0.8 * 1.2) in the middle
+        let (low, high) = inference.confidence_band(model.as_ref(), 3.0);
+        assert_eq!(
+            (to_precision(low, 4), to_precision(high, 4)),
+            (2.727, 5.273)
+        );
+        let (low, high) = inference.prediction_band(model.as_ref(), 3.0);
+        assert_eq!(
+            (to_precision(low, 4), to_precision(high, 4)),
+            (0.8819, 7.1181)
+        );
+        // and wider further out
+        let (low, high) = inference.confidence_band(model.as_ref(), 5.0);
+        assert!(high - low > 5.273 - 2.727);
+    }
+
+    #[test]
+    fn transformed_models() {
+        // the bands of a curve fitted on ln(y) stay positive and around the curve
+        let x_values: Vec<f64> = (1..=30).map(f64::from).collect();
+        let y_values: Vec<f64> = x_values
+            .iter()
+            .enumerate()
+            .map(|(i, x)| 20.0 * (-0.05 * x).exp() * if i % 2 == 0 { 1.1 } else { 0.9 })
+            .collect();
+        let model = ModelKind::Exponential.fit(&x_values, &y_values).unwrap();
+        let inference = Inference::of(model.as_ref(), &x_values, &y_values, 0.9).unwrap();
+        assert_eq!(inference.coefficients[0].term, "1");
+        assert!(inference.coefficients[1].p < 0.001);
+        for x in [1.0, 15.0, 40.0] {
+            let (low, high) = inference.prediction_band(model.as_ref(), x);
+            assert!(0.0 < low && low < model.predict(x) && model.predict(x) < high);
+        }
+
+        // too few points to say anything
+        let model = ModelKind::Polynomial(2)
+            .fit(&[1.0, 2.0, 3.0], &[1.0, 4.0, 9.0])
+            .unwrap();
+        assert!(Inference::of(model.as_ref(), &[1.0, 2.0, 3.0], &[1.0, 4.0, 9.0], 0.95).is_err());
+    }
+}
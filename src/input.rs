@@ -0,0 +1,188 @@
+// Opening PGN input
+// lichess ships its database compressed, so we decompress on the fly instead of needing
+// hundreds of gigabytes of disk space for the plain text.
+
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zstd,
+    Bzip2,
+    Gzip,
+}
+
+impl Compression {
+    /// Guess the compression from the file extension, e.g. `.pgn.zst`.
+    pub fn from_extension(path: &str) -> Option<Compression> {
+        match Path::new(path).extension()?.to_str()? {
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            "gz" => Some(Compression::Gzip),
+            "pgn" => Some(Compression::None),
+            _ => None,
+        }
+    }
+
+    /// Guess the compression from the first few bytes of the stream.
+    pub fn from_magic(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if bytes.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Expand glob patterns like `games/2023-1*.pgn.zst` into a sorted list of files.
+/// Plain paths (and `-`) are passed through untouched, so a typo still shows up as a missing file later.
+pub fn expand(patterns: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut paths = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            paths.push(pattern.clone());
+            continue;
+        }
+        let mut matches = glob::glob(pattern)?
+            .map(|entry| entry.map(|path| path.to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if matches.is_empty() {
+            return Err(format!("No files match {}", pattern).into());
+        }
+        matches.sort();
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+/// Open a PGN source for reading. `-` reads from stdin.
+/// Compressed files are detected by extension, falling back to magic bytes.
+pub fn open(path: &str) -> io::Result<Box<dyn BufRead + Send>> {
+    if path == "-" {
+        return decompress(BufReader::new(io::stdin()), None);
+    }
+    let file = BufReader::new(File::open(path)?);
+    decompress(file, Compression::from_extension(path))
+}
+
+/// Wrap `reader` in the right decoder. If `compression` is unknown, sniff it from the stream.
+pub fn decompress<R: BufRead + Send + 'static>(
+    mut reader: R,
+    compression: Option<Compression>,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let compression = match compression {
+        Some(c) => c,
+        None => Compression::from_magic(reader.fill_buf()?),
+    };
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Zstd => {
+            let mut decoder = zstd::Decoder::with_buffer(reader)?;
+            // the lichess dumps are compressed with a long window
+            decoder.window_log_max(31)?;
+            Box::new(BufReader::new(decoder))
+        }
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, expand, Compression};
+    use std::{
+        fs::{create_dir_all, remove_dir_all, File},
+        io::{Cursor, Read, Write},
+    };
+
+    const PGN: &str =
+        "[Event \"Rated Bullet game\"]\n[TimeControl \"60+0\"]\n\n1. e4 { [%clk 0:01:00] } 1-0\n\n";
+
+    fn roundtrip(compressed: Vec<u8>, compression: Option<Compression>) -> String {
+        let mut out = String::new();
+        decompress(Cursor::new(compressed), compression)
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(
+            Compression::from_extension("lichess_db_standard_rated_2023-10.pgn.zst"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::from_extension("games.pgn.bz2"),
+            Some(Compression::Bzip2)
+        );
+        assert_eq!(
+            Compression::from_extension("games.pgn.gz"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_extension("games.pgn"),
+            Some(Compression::None)
+        );
+        assert_eq!(Compression::from_extension("games"), None);
+    }
+
+    #[test]
+    fn glob_expansion() {
+        let dir = std::env::temp_dir().join("chess-analysis-glob-test");
+        create_dir_all(&dir).unwrap();
+        for name in ["2023-11.pgn", "2023-10.pgn", "2023-10.pgn.zst", "notes.txt"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let dir_str = dir.to_str().unwrap();
+
+        let paths = expand(&[format!("{}/*.pgn", dir_str), "-".to_string()]).unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                format!("{}/2023-10.pgn", dir_str),
+                format!("{}/2023-11.pgn", dir_str),
+                "-".to_string(),
+            ]
+        );
+        assert!(expand(&[format!("{}/*.pgn.bz2", dir_str)]).is_err());
+        // no wildcards, so no check that it exists
+        assert_eq!(
+            expand(&["missing.pgn".to_string()]).unwrap(),
+            vec!["missing.pgn"]
+        );
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn decompression() {
+        let zst = zstd::encode_all(PGN.as_bytes(), 3).unwrap();
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        bz.write_all(PGN.as_bytes()).unwrap();
+        let bz = bz.finish().unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(PGN.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+
+        for compressed in [zst, bz, gz, PGN.as_bytes().to_vec()] {
+            // once sniffed from the magic bytes, and once told explicitly
+            let sniffed = Compression::from_magic(&compressed);
+            assert_eq!(roundtrip(compressed.clone(), None), PGN);
+            assert_eq!(roundtrip(compressed, Some(sniffed)), PGN);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Time usage analysis for chess games.
+//!
+//! Read PGNs (or a saved dataset) into a [`reader::GameReader`], then run the statistics in
+//! [`analysis`] or draw graphs with [`plots::plotter::generate_plots`].
+//! The `chess-analysis` binary is a thin command line wrapper around this crate.
+
+pub mod analysis;
+pub mod annotations;
+pub mod bootstrap;
+pub mod dataset;
+pub mod export;
+pub mod filter;
+pub mod inference;
+pub mod input;
+pub mod moves;
+pub mod pipeline;
+pub mod plots;
+pub mod quality;
+pub mod quantile;
+pub mod reader;
+pub mod regression;
+pub mod usage;
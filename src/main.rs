@@ -1,19 +1,26 @@
 use clap::Parser;
-use pgn_reader::BufferedReader;
-use std::{fs::File, io::BufReader, time::Instant};
+use std::{path::Path, thread, time::Instant};
 
-mod analysis;
 mod args;
-mod plots;
-mod reader;
 
-use args::Args;
-use reader::GameReader;
+use args::{AnalyzeArgs, Args, CollectArgs, Command, DataArgs, PlotArgs};
+use chess_analysis::bootstrap::{clusters, Bootstrap};
+use chess_analysis::export::{export_moves, ExportFormat};
+use chess_analysis::inference::Inference;
+use chess_analysis::moves::{Color, MoveContext, MoveStore, Phase};
+use chess_analysis::quality::{error_rates, Judgement};
+use chess_analysis::quantile::{ordinal, QuantileModel};
+use chess_analysis::reader::{BadClocks, GameReader};
+use chess_analysis::usage::{self, Distribution};
+use chess_analysis::{dataset, input, pipeline};
 
-use plots::plotter::generate_plots;
+use chess_analysis::plots::plotter::generate_plots;
 
-use crate::analysis::{
-    determination, generate_residuals, quadratic_regression, standard_deviation, to_precision,
+use chess_analysis::analysis::{
+    model_standard_error, quadratic_regression, standard_deviation, to_precision,
+};
+use chess_analysis::regression::{
+    rank_models, Criterion, ModelKind, ModelScore, RegressionModel, CANDIDATES,
 };
 
 // TODO:
@@ -22,64 +29,254 @@ use crate::analysis::{
 // actually finish the fucking assignment LMAO
 // idk what else lol.
 
-/*
-read 4096 pgns out of the file
-relatively light, cant be more than 50mb
-and then feed that to 4 threads
-and keep a buffer of 4 1024 pgns to pass out from the main thread
- */
-
-// cargo run --release -- games/oct-2023-games.pgn -m 1000 --min-rating 1000 --max-rating 2000 --time-control 600+0
+// cargo run --release -- plot games/oct-2023-games.pgn -o plots --max-games 1000 --min-rating 1000 --max-rating 2000 --time-control 600+0 -a
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // TODO: arg validation
     let args = Args::parse();
-    // open the file parsed from clap
-    let mut game_reader = GameReader::new(&args);
-    data_collection(&mut game_reader);
-    plots(&game_reader);
-    one_var_analysis(&game_reader);
-    analysis(&game_reader)?;
-
-    let mut c = 0;
-    game_reader.time_data[150].iter().for_each(|t| {
-        if *t == 10 {
-            c += 1;
+    match args.command {
+        Command::Collect(args) => {
+            let game_reader = read_data(&args.data)?;
+            save_dataset(&game_reader, &args);
+            export(&game_reader, &args);
         }
-    });
-    let conditional = c as f32 / game_reader.time_data[150].len() as f32;
-    println!(
-        "Probability that TTM < 10s, given 150s left: {}",
-        conditional
-    );
+        Command::Analyze(args) => {
+            let game_reader = read_data(&args.data)?;
+            one_var_analysis(&game_reader, &args);
+            analysis(&game_reader, &args)?;
+            if args.errors {
+                error_analysis(&game_reader, &args);
+            }
+            if args.by_move {
+                usage_analysis(&game_reader, &args);
+            }
+        }
+        Command::Plot(args) => {
+            let game_reader = read_data(&args.data)?;
+            plots(&game_reader, &args);
+        }
+        Command::Report(args) => {
+            let game_reader = read_data(&args.data)?;
+            report(&game_reader);
+            player_reports(&game_reader)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load a saved dataset if we were given one, otherwise read the PGN(s)
+fn read_data(args: &DataArgs) -> Result<GameReader, Box<dyn std::error::Error>> {
+    let mut game_reader = match args.input.as_slice() {
+        [path] if dataset::is_dataset(path) => {
+            let flags = args.reading_flags();
+            if !flags.is_empty() {
+                return Err(format!(
+                    "{} can't be used with a saved dataset, it was filtered when it was collected. \
+                     Collect a new dataset with the filters instead.",
+                    flags.join(", ")
+                )
+                .into());
+            }
+            load_dataset(path)?
+        }
+        _ => {
+            let mut game_reader = GameReader::new(args.reader_config()?);
+            data_collection(&mut game_reader, args);
+            game_reader
+        }
+    };
+    game_reader.normalize = args.normalize;
+    Ok(game_reader)
+}
+
+fn report(game_reader: &GameReader) {
+    println!();
+    println!(" --- Report --- ");
+    println!();
 
-    let expected = game_reader
-        .time_data
+    let time_data = game_reader.time_data();
+    // only makes sense for time controls with more than 150 seconds
+    if let Some(moves) = time_data.get(150).filter(|_| !game_reader.normalize) {
+        let c = moves.iter().filter(|t| **t == 10.0).count();
+        let conditional = c as f32 / moves.len() as f32;
+        println!(
+            "Probability that TTM < 10s, given 150s left: {}",
+            conditional
+        );
+    }
+
+    let expected = time_data
         .iter()
         .enumerate()
-        .map(|(i, v)| {
-            let p = 1.0 / game_reader.max_allowed_time as f32;
+        .map(|(i, _)| {
+            let p = 1.0 / game_reader.max_clock() as f32;
             i as f32 * p
         })
         .sum::<f32>();
     println!("Expected time remaining: {:.2}", expected);
-    let (x_values, _y_values): (Vec<i32>, Vec<i32>) = game_reader
-        .time_data
-        .iter()
-        .enumerate()
-        .flat_map(|(x, row)| row.iter().map(move |&y| (x as i32, y)))
-        .unzip();
+    let (x_values, _) = game_reader.xy();
 
-    let average = x_values.iter().sum::<i32>() as f32 / x_values.len() as f32;
+    let average = x_values.iter().sum::<f64>() / x_values.len() as f64;
     println!("{}", x_values.len());
     println!("Average time remaining: {:.2}", average);
 
-    let stdev = standard_deviation(&x_values.iter().map(|f| *f as f64).collect::<Vec<_>>());
+    let stdev = standard_deviation(&x_values);
     println!("Standard Deviation: {}", stdev);
     println!("Variance: {}", stdev.powi(2));
+
+    // only analysed games have evals
+    let losses = game_reader
+        .moves
+        .iter()
+        .filter_map(|m| m.centipawn_loss())
+        .collect::<Vec<_>>();
+    if !losses.is_empty() {
+        println!(
+            "Average centipawn loss: {:.2} ({} moves with evaluations)",
+            losses.iter().sum::<i32>() as f32 / losses.len() as f32,
+            losses.len()
+        );
+    }
+
+    // does the kind of move or the number of options change how long people think?
+    let (_, y_values) = game_reader.xy();
+    let moves: Vec<_> = game_reader.moves.iter().zip(y_values).collect();
+    let average_time = |keep: &dyn Fn(&MoveContext) -> bool| {
+        let times: Vec<f64> = moves
+            .iter()
+            .filter(|(m, _)| keep(&m.context))
+            .map(|(_, t)| *t)
+            .collect();
+        if times.is_empty() {
+            "-".to_string()
+        } else {
+            format!("{:.2}", times.iter().sum::<f64>() / times.len() as f64)
+        }
+    };
+    println!();
+    println!("Average time taken ({}):", game_reader.clock_unit());
+    println!("  Captures: {}", average_time(&|c| c.capture));
+    println!("  Checks: {}", average_time(&|c| c.check));
+    println!("  Promotions: {}", average_time(&|c| c.promotion));
+    println!("  Castling: {}", average_time(&|c| c.castle));
+    println!(
+        "  Other moves: {}",
+        average_time(&|c| !(c.capture || c.check || c.promotion || c.castle))
+    );
+    println!("Average time taken by number of legal moves:");
+    for (min, max) in [(1, 10), (11, 20), (21, 30), (31, 40), (41, u16::MAX)] {
+        let label = if max == u16::MAX {
+            format!("{}+", min)
+        } else {
+            format!("{}-{}", min, max)
+        };
+        println!(
+            "  {}: {}",
+            label,
+            average_time(&|c| c.legal_moves.is_some_and(|n| (min..=max).contains(&n)))
+        );
+    }
+}
+
+/// Compare each tracked player's moves with everyone else's.
+fn player_reports(game_reader: &GameReader) -> Result<(), Box<dyn std::error::Error>> {
+    for (i, name) in game_reader.config.players.0.iter().enumerate() {
+        println!();
+        println!(" --- Player: {} --- ", name);
+        println!();
+
+        let player = Some(i as u16);
+        let moves = game_reader.moves.filtered(|m| m.player == player);
+        let population = game_reader.moves.filtered(|m| m.player != player);
+        if moves.is_empty() {
+            println!("No moves.");
+            continue;
+        }
+        let mut games = moves.game_id.clone();
+        games.dedup();
+        println!(
+            "{} moves in {} games, compared with {} moves by everyone else.",
+            moves.len(),
+            games.len(),
+            population.len()
+        );
+
+        let (x_values, y_values) = moves.xy(game_reader.normalize);
+        let (population_x, population_y) = population.xy(game_reader.normalize);
+        let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+        println!(
+            "Average time left: {:.2} (everyone else: {:.2})",
+            mean(&x_values),
+            mean(&population_x)
+        );
+        println!(
+            "Average time taken: {:.2} (everyone else: {:.2})",
+            mean(&y_values),
+            mean(&population_y)
+        );
+        // a quadratic needs a few points to fit
+        if x_values.len() >= 3 && population_x.len() >= 3 {
+            let curve = |(a, b, c): (f64, f64, f64)| {
+                format!(
+                    "{}x^2 {:+}x {:+}",
+                    to_precision(a, 4),
+                    to_precision(b, 4),
+                    to_precision(c, 4)
+                )
+            };
+            println!(
+                "Quadratic Regression: {}",
+                curve(quadratic_regression(&x_values, &y_values)?)
+            );
+            println!(
+                "Everyone else: {}",
+                curve(quadratic_regression(&population_x, &population_y)?)
+            );
+        }
+
+        // average time taken for ~10 ranges of time left, side by side
+        let max_clock = game_reader.max_clock();
+        let player_data = moves.time_data(max_clock, game_reader.normalize);
+        let population_data = population.time_data(max_clock, game_reader.normalize);
+        let width = player_data.len().div_ceil(10);
+        println!();
+        println!(
+            "{:>16} {:>8} {:>12} {:>14}",
+            format!(
+                "Time Left ({})",
+                if game_reader.normalize { "%" } else { "S" }
+            ),
+            "Moves",
+            "Avg TTM",
+            "Everyone else"
+        );
+        for (i, (range, population_range)) in player_data
+            .chunks(width)
+            .zip(population_data.chunks(width))
+            .enumerate()
+        {
+            let times = range.concat();
+            if times.is_empty() {
+                continue;
+            }
+            let population_times = population_range.concat();
+            println!(
+                "{:>16} {:>8} {:>12.2} {:>14}",
+                format!("{}-{}", i * width, (i + 1) * width - 1),
+                times.len(),
+                mean(&times),
+                if population_times.is_empty() {
+                    "-".to_string()
+                } else {
+                    format!("{:.2}", mean(&population_times))
+                }
+            );
+        }
+    }
     Ok(())
 }
-fn one_var_analysis(game_reader: &GameReader) {
-    if game_reader.args.x_percentile.is_none() && game_reader.args.y_percentile.is_none() {
+
+fn one_var_analysis(game_reader: &GameReader, args: &AnalyzeArgs) {
+    if args.x_percentile.is_none() && args.y_percentile.is_none() {
         return;
     }
 
@@ -87,63 +284,128 @@ fn one_var_analysis(game_reader: &GameReader) {
     println!(" --- One variable analysis --- ");
     println!();
 
-    let (mut x_values, mut y_values): (Vec<i32>, Vec<i32>) = game_reader
-        .time_data
-        .iter()
-        .enumerate()
-        .flat_map(|(x, row)| row.iter().map(move |&y| (x as i32, y)))
-        .unzip();
-    x_values.sort();
-    x_values.reverse();
-    y_values.sort();
+    let (mut x_values, mut y_values) = game_reader.xy();
+    x_values.sort_by(|a, b| b.total_cmp(a));
+    y_values.sort_by(f64::total_cmp);
+    let unit = if game_reader.normalize {
+        "percent of the starting clock"
+    } else {
+        "seconds"
+    };
 
-    if let Some(percentile) = game_reader.args.x_percentile {
+    if let Some(percentile) = args.x_percentile {
         let idx = (x_values.len() as f64 * (percentile as f64 / 100.0)) as usize;
         println!(
-            "The {}th percentile of time left is {} seconds remaining",
-            percentile, x_values[idx]
+            "The {} percentile of time left is {:.2} {} remaining",
+            ordinal(percentile as f64),
+            x_values[idx],
+            unit
         );
     }
-    if let Some(percentile) = game_reader.args.y_percentile {
+    if let Some(percentile) = args.y_percentile {
         let idx = (y_values.len() as f64 * (percentile as f64 / 100.0)) as usize;
         println!(
-            "The {}th percentile of time taken is {} seconds to move",
-            percentile, y_values[idx]
+            "The {} percentile of time taken is {:.2} {} to move",
+            ordinal(percentile as f64),
+            y_values[idx],
+            unit
         );
     }
 }
 
 // whatever (bladee)
 
-fn plots(game_reader: &GameReader) {
+fn plots(game_reader: &GameReader, args: &PlotArgs) {
     println!();
     println!(" --- Plots --- ");
     println!();
     println!("Now creating data plots... This shouldn't take long. ");
-    generate_plots(game_reader)
+    generate_plots(game_reader, &args.plot_options())
         .unwrap_or_else(|e| println!("An error occurred generating plots:\n{}", e));
 
     println!("Successfully generated plots.");
 }
 
-fn data_collection(game_reader: &mut GameReader) {
+fn load_dataset(path: &str) -> Result<GameReader, Box<dyn std::error::Error>> {
+    println!();
+    println!(" --- Data Collection --- ");
+    println!();
+    println!("Loading saved dataset from {}...", path);
+    let game_reader = dataset::load(Path::new(path))?;
+    println!(
+        "Dataset was collected with filters: {}. A total of {} games were analyzed out of {}.",
+        game_reader.config.filter.description(),
+        game_reader.games_analyzed,
+        game_reader.total_games
+    );
+    println!(
+        "A total of {} moves were analyzed.",
+        game_reader.moves_analyzed
+    );
+    Ok(game_reader)
+}
+
+fn save_dataset(game_reader: &GameReader, args: &CollectArgs) {
+    let Some(output) = &args.output else {
+        return;
+    };
+    println!();
+    println!("Saving dataset to {}...", output);
+    match dataset::save(game_reader, Path::new(output)) {
+        Ok(()) => println!("Successfully saved dataset."),
+        Err(e) => println!("An error occurred saving the dataset:\n{}", e),
+    }
+}
+
+fn export(game_reader: &GameReader, args: &CollectArgs) {
+    let Some(output) = &args.export else {
+        return;
+    };
+    println!();
+    println!(" --- Export --- ");
+    println!();
+    let path = Path::new(output);
+    let format = args
+        .export_format
+        .unwrap_or_else(|| ExportFormat::from_path(path));
+    println!("Exporting moves to {}...", output);
+    match export_moves(&game_reader.moves, path, format) {
+        Ok(count) => println!("Successfully exported {} moves.", count),
+        Err(e) => println!("An error occurred exporting moves:\n{}", e),
+    }
+}
+
+fn data_collection(game_reader: &mut GameReader, args: &DataArgs) {
     println!();
     println!(" --- Data Collection --- ");
     println!();
-    let games = File::open(&game_reader.args.input).expect("Error reading PGN file. :( Exiting...");
-    // open a bufreader
-    let buf = BufReader::new(games);
-    println!("Successfully found PGN file!");
+    let files = input::expand(&args.input).expect("Error finding PGN files. :( Exiting...");
+    println!("Found {} PGN file(s)!", files.len());
     println!("NOTE: games are read sequentially read and not randomly sampled.");
 
-    // now, we will actually read the file and the games
-    let mut reader = BufferedReader::new(buf);
-    println!("Reading all games. This will take a moment... Or a few, if you have a lot of games.");
+    // now, we will actually read the files and the games
+    let threads = args.threads.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    println!("Reading all games on {} thread(s). This will take a moment... Or a few, if you have a lot of games.", threads);
     println!();
     let start = Instant::now();
-    reader
-        .read_all(game_reader)
-        .unwrap_or_else(|e| println!("An error occurred reading games:\n{}", e));
+    // (file, games analyzed, total games) for the summary
+    let mut file_counts = Vec::new();
+    for file in files {
+        // open a bufreader, decompressing if we need to
+        let buf = input::open(&file).expect("Error reading PGN file. :( Exiting...");
+        let (analyzed_before, total_before) = (game_reader.games_analyzed, game_reader.total_games);
+        pipeline::read_games(buf, game_reader, threads)
+            .unwrap_or_else(|e| println!("An error occurred reading games from {}:\n{}", file, e));
+        file_counts.push((
+            file,
+            game_reader.games_analyzed - analyzed_before,
+            game_reader.total_games - total_before,
+        ));
+    }
     let duration = start.elapsed();
 
     // print some helpful information for the user
@@ -160,38 +422,410 @@ fn data_collection(game_reader: &mut GameReader) {
         "A total of {} moves were analyzed.",
         game_reader.moves_analyzed
     );
+    if game_reader.bad_clock_games > 0 {
+        println!(
+            "{} games had clocks that couldn't be read and were {}.",
+            game_reader.bad_clock_games,
+            match args.bad_clocks {
+                BadClocks::Skip => "skipped",
+                BadClocks::Flag => "kept without them",
+            }
+        );
+    }
+    if game_reader.clock_checks > 0 {
+        println!(
+            "%clk and %emt disagreed by more than a second on {} of the {} moves that had both.",
+            game_reader.clock_mismatches, game_reader.clock_checks
+        );
+    }
+    if file_counts.len() > 1 {
+        println!();
+        for (file, analyzed, total) in file_counts {
+            println!("{}: {} games analyzed out of {}.", file, analyzed, total);
+        }
+    }
 }
 
-fn analysis(game_reader: &GameReader) -> Result<(), Box<dyn std::error::Error>> {
-    let (x_values, y_values): (Vec<f64>, Vec<f64>) = game_reader
-        .time_data
-        .iter()
-        .enumerate()
-        .flat_map(|(x, row)| row.iter().map(move |&y| (x as f64, y as f64)))
-        .unzip();
+fn analysis(
+    game_reader: &GameReader,
+    args: &AnalyzeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
     // print cool little title
     println!();
     println!(" --- Regression Analysis --- ");
     println!();
 
-    let line = quadratic_regression(&x_values, &y_values)?;
-    let det = determination(&x_values, &y_values)?;
-    let residuals = generate_residuals(&x_values, &y_values)?;
-    let stdev = standard_deviation(&residuals);
+    let (x_values, y_values) = game_reader.xy();
+    // the candidates are only ranked when there's a model to pick
+    let model = match args.model {
+        Some(model) => model,
+        None => {
+            let ranked = rank_models(
+                &game_reader.moves.game_id,
+                &x_values,
+                &y_values,
+                &CANDIDATES,
+                args.folds,
+                args.rank_by,
+            );
+            print_model_ranking(&ranked, args);
+            match ranked.first() {
+                Some(score) => score.kind,
+                None => {
+                    println!("None of the candidate models could be fitted.");
+                    println!();
+                    return Ok(());
+                }
+            }
+        }
+    };
+    regression(&x_values, &y_values, model, args)?;
+    if let Some(resamples) = args.bootstrap {
+        bootstrap_analysis(game_reader, args, model, resamples);
+    }
+    if args.by_color {
+        for color in [Color::White, Color::Black] {
+            println!(" -- {} -- ", color);
+            let moves = game_reader.moves.filtered(|m| m.color == color);
+            if moves.is_empty() {
+                println!("No moves.");
+                println!();
+                continue;
+            }
+            let (x_values, y_values) = moves.xy(game_reader.normalize);
+            regression(&x_values, &y_values, model, args)?;
+        }
+    }
+    Ok(())
+}
+
+fn error_analysis(game_reader: &GameReader, args: &AnalyzeArgs) {
+    println!(" --- Errors by Time Left --- ");
+    println!();
+
+    let judgement_data = game_reader.judgement_data(args.judgement);
+    let judged: usize = judgement_data.iter().map(|moves| moves.len()).sum();
+    if judged == 0 {
+        println!("No moves with evaluations. (Errors need [%eval] comments)");
+        println!();
+        return;
+    }
+    let all_moves = [judgement_data.concat()];
+    println!("Judged moves: {}", judged);
+    for judgement in Judgement::ERRORS {
+        let rate = error_rates(&all_moves, judgement)[0].unwrap_or_default();
+        println!(
+            "{} rate: {}%",
+            judgement.name(),
+            to_precision(rate * 100.0, 2)
+        );
+    }
+    println!();
+
+    // the buckets are one second (or percent) each, which is too fine to read, so group them into ~10 ranges
+    let width = judgement_data.len().div_ceil(10);
+    println!(
+        "{:>16} {:>8} {:>12} {:>12} {:>12}",
+        format!(
+            "Time Left ({})",
+            if game_reader.normalize { "%" } else { "S" }
+        ),
+        "Moves",
+        "Inaccuracy",
+        "Mistake",
+        "Blunder"
+    );
+    for (i, range) in judgement_data.chunks(width.max(1)).enumerate() {
+        let moves = [range.concat()];
+        if moves[0].is_empty() {
+            continue;
+        }
+        let rates: Vec<String> = Judgement::ERRORS
+            .iter()
+            .map(|judgement| {
+                let rate = error_rates(&moves, *judgement)[0].unwrap_or_default();
+                format!("{}%", to_precision(rate * 100.0, 2))
+            })
+            .collect();
+        println!(
+            "{:>16} {:>8} {:>12} {:>12} {:>12}",
+            format!("{}-{}", i * width, (i + 1) * width - 1),
+            moves[0].len(),
+            rates[0],
+            rates[1],
+            rates[2]
+        );
+    }
+    println!();
+}
+
+fn usage_analysis(game_reader: &GameReader, args: &AnalyzeArgs) {
+    println!(" --- Time Usage by Move Number and Phase --- ");
+    println!();
+    println!(" -- All moves -- ");
+    print_usage(&game_reader.moves, game_reader, args.max_move);
+    for (group, moves) in usage::groups(&game_reader.moves, args.rating_band) {
+        println!(" -- {} -- ", group);
+        print_usage(&moves, game_reader, args.max_move);
+    }
+}
+
+fn print_usage(moves: &MoveStore, game_reader: &GameReader, max_move: u16) {
+    let row = |label: String, distribution: Option<Distribution>| match distribution {
+        Some(d) => println!(
+            "{:>12} {:>8} {:>8.2} {:>8.2} {:>8.2}",
+            label, d.count, d.mean, d.median, d.p90
+        ),
+        None => println!("{:>12} {:>8}", label, 0),
+    };
+    println!(
+        "{:>12} {:>8} {:>8} {:>8} {:>8}   (time taken in {})",
+        "",
+        "Moves",
+        "Mean",
+        "Median",
+        "90th",
+        game_reader.clock_unit()
+    );
+    for (phase, times) in Phase::ALL
+        .iter()
+        .zip(usage::by_phase(moves, game_reader.normalize))
+    {
+        row(phase.to_string(), Distribution::of(&times));
+    }
+    let by_move = usage::by_move_number(moves, game_reader.normalize, max_move);
+    for (move_number, points) in by_move.iter().enumerate().skip(1) {
+        // the last one has every move past it too
+        let label = if move_number == max_move as usize {
+            format!("move {}+", move_number)
+        } else {
+            format!("move {}", move_number)
+        };
+        let times: Vec<f64> = points.iter().map(|(_, time_taken)| *time_taken).collect();
+        if !times.is_empty() {
+            row(label, Distribution::of(&times));
+        }
+    }
+    println!();
+}
+
+fn bootstrap_analysis(
+    game_reader: &GameReader,
+    args: &AnalyzeArgs,
+    model: ModelKind,
+    resamples: usize,
+) {
+    println!(" --- Bootstrap --- ");
+    println!();
+
+    // percentiles the same way the one variable analysis does them, time left counts down
+    let percentile = |values: &[f64], percentile: i32, descending: bool| {
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        if descending {
+            sorted.reverse();
+        }
+        let idx = (sorted.len() as f64 * (percentile as f64 / 100.0)) as usize;
+        sorted[idx.min(sorted.len() - 1)]
+    };
+    let median = |values: &[f64]| Distribution::of(values).map_or(f64::NAN, |d| d.median);
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+
+    let mut names = vec![
+        "Mean time left".to_string(),
+        "Median time left".to_string(),
+        "Mean time taken".to_string(),
+        "Median time taken".to_string(),
+    ];
+    if let Some(p) = args.x_percentile {
+        names.push(format!("{} percentile of time left", ordinal(p as f64)));
+    }
+    if let Some(p) = args.y_percentile {
+        names.push(format!("{} percentile of time taken", ordinal(p as f64)));
+    }
+    let (x_values, y_values) = game_reader.xy();
+    if let Ok(fitted) = model.fit(&x_values, &y_values) {
+        for term in fitted.term_names() {
+            names.push(format!("Coefficient of {}", term));
+        }
+        names.push("R^2".to_string());
+    }
+
+    let statistic = |x: &[f64], y: &[f64]| {
+        if x.is_empty() {
+            return None;
+        }
+        let mut values = vec![mean(x), median(x), mean(y), median(y)];
+        if let Some(p) = args.x_percentile {
+            values.push(percentile(x, p, true));
+        }
+        if let Some(p) = args.y_percentile {
+            values.push(percentile(y, p, false));
+        }
+        let fitted = model.fit(x, y).ok()?;
+        values.extend(fitted.linear_coefficients());
+        values.push(fitted.r_squared(x, y));
+        Some(values)
+    };
+    let bootstrap = Bootstrap {
+        resamples,
+        seed: args.seed,
+        confidence: args.confidence,
+    };
+    let Some(summary) = bootstrap.run(&game_reader.moves.game_id, &x_values, &y_values, statistic)
+    else {
+        println!("Not enough data to bootstrap.");
+        println!();
+        return;
+    };
 
     println!(
-        "Quadratic Regression: {}x^2 {}{}x {}{}",
-        to_precision(line.0, 4),
-        if line.1.is_sign_positive() { "+" } else { "" },
-        to_precision(line.1, 4),
-        if line.2.is_sign_positive() { "+" } else { "" },
-        to_precision(line.2, 4)
+        "{} resamples of {} games (seed {}), times in {}, {} regression:",
+        summary.resamples,
+        clusters(&game_reader.moves.game_id).len(),
+        args.seed,
+        game_reader.clock_unit(),
+        model
     );
+    println!(
+        "{:>30} {:>12} {:>12} {:>28}",
+        "Statistic",
+        "Estimate",
+        "Std. Error",
+        format!("{}% CI", to_precision(args.confidence * 100.0, 2))
+    );
+    for (name, interval) in names.iter().zip(&summary.intervals) {
+        println!(
+            "{:>30} {:>12} {:>12} {:>28}",
+            name,
+            format_number(interval.estimate),
+            format_number(interval.standard_error),
+            format!(
+                "{} to {}",
+                format_number(interval.low),
+                format_number(interval.high)
+            )
+        );
+    }
+    println!();
+}
+
+// four decimals, or scientific notation for the tiny coefficients that would round off to 0
+fn format_number(value: f64) -> String {
+    if value.abs() < 0.0001 && value != 0.0 {
+        format!("{:.4e}", value)
+    } else {
+        to_precision(value, 4).to_string()
+    }
+}
+
+fn print_model_ranking(ranked: &[ModelScore], args: &AnalyzeArgs) {
+    println!(
+        "Candidate models, ranked by {}:",
+        match args.rank_by {
+            Criterion::Cv => format!("{}-fold cross-validated RMSE", args.folds),
+            Criterion::Aic => "AIC".to_string(),
+            Criterion::Bic => "BIC".to_string(),
+        }
+    );
+    println!(
+        "{:>4} {:>10} {:>8} {:>14} {:>14} {:>10}",
+        "Rank", "Model", "R^2", "AIC", "BIC", "CV RMSE"
+    );
+    for (i, score) in ranked.iter().enumerate() {
+        println!(
+            "{:>4} {:>10} {:>8} {:>14} {:>14} {:>10}",
+            i + 1,
+            score.kind.to_string(),
+            to_precision(score.r_squared, 4),
+            to_precision(score.aic, 2),
+            to_precision(score.bic, 2),
+            to_precision(score.cv_error, 4)
+        );
+    }
+    println!();
+}
+
+fn regression(
+    x_values: &[f64],
+    y_values: &[f64],
+    kind: ModelKind,
+    args: &AnalyzeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let model = kind.fit(x_values, y_values)?;
+    let det = model.r_squared(x_values, y_values);
+    let stdev = model_standard_error(model.as_ref(), x_values, y_values);
+
+    println!("{}: {}", model.name(), model.equation());
 
     println!("Coefficient of Determination (R^2) = {}", det);
-    println!("Correlation (r) = {}", to_precision(det.sqrt(), 4));
+    // a model that fits worse than the mean has a negative R^2, call that no correlation
+    println!("Correlation (r) = {}", to_precision(det.max(0.0).sqrt(), 4));
     println!("Residuals Standard Deviation: {stdev}");
-
     println!();
+
+    match Inference::of(model.as_ref(), x_values, y_values, args.confidence) {
+        Ok(inference) => print_coefficients(&inference, model.fitted_variable()),
+        Err(e) => {
+            println!("No standard errors: {}", e);
+            println!();
+        }
+    }
+    quantile_regression(x_values, y_values, kind, &args.quantiles);
     Ok(())
 }
+
+fn quantile_regression(x_values: &[f64], y_values: &[f64], model: ModelKind, quantiles: &[f64]) {
+    if quantiles.is_empty() {
+        return;
+    }
+    // least squares goes for the mean, which the long thinks drag up. these go through the percentiles
+    println!("Quantile Regression:");
+    for percentile in quantiles {
+        match QuantileModel::fit(model, percentile / 100.0, x_values, y_values) {
+            Ok(curve) => {
+                println!("{}: {}", curve.name(), curve.equation());
+                println!(
+                    "  Moves below the curve: {}%, Pseudo R^2 = {}",
+                    to_precision(curve.share_below(x_values, y_values) * 100.0, 2),
+                    to_precision(curve.pseudo_r_squared(x_values, y_values), 4)
+                );
+            }
+            Err(e) => println!("{} percentile: {}", ordinal(*percentile), e),
+        }
+    }
+    println!();
+}
+
+fn print_coefficients(inference: &Inference, fitted_variable: &str) {
+    if fitted_variable != "y" {
+        println!("Fitted on {}:", fitted_variable);
+    }
+    println!(
+        "{:>8} {:>14} {:>12} {:>10} {:>8} {:>28}",
+        "Term",
+        "Coefficient",
+        "Std. Error",
+        "t",
+        "p",
+        format!("{}% CI", to_precision(inference.confidence * 100.0, 2))
+    );
+    for coefficient in &inference.coefficients {
+        let (low, high) = inference.interval(coefficient);
+        println!(
+            "{:>8} {:>14} {:>12} {:>10} {:>8} {:>28}",
+            coefficient.term,
+            format_number(coefficient.estimate),
+            format_number(coefficient.standard_error),
+            to_precision(coefficient.t, 2),
+            if coefficient.p < 0.0001 {
+                "<0.0001".to_string()
+            } else {
+                to_precision(coefficient.p, 4).to_string()
+            },
+            format!("{} to {}", format_number(low), format_number(high))
+        );
+    }
+    println!("Degrees of freedom: {}", inference.degrees_of_freedom);
+    println!();
+}
@@ -0,0 +1,583 @@
+// Per-move data
+// every move the reader keeps ends up here along with the game it came from,
+// so new questions don't need a new pass over the PGN.
+
+use crate::annotations::Eval;
+use crate::quality::{judge, Judgement, JudgementMethod};
+use clap::ValueEnum;
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    /// The side that plays half-move `ply` (1 is white's first move).
+    pub fn of_ply(ply: u16) -> Color {
+        if ply % 2 == 1 {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::White => write!(f, "white"),
+            Color::Black => write!(f, "black"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown,
+}
+
+impl GameResult {
+    /// Parse the value of a `Result` header. Anything unexpected (like `*`) is unknown.
+    pub fn from_header(value: &str) -> GameResult {
+        match value {
+            "1-0" => GameResult::WhiteWins,
+            "0-1" => GameResult::BlackWins,
+            "1/2-1/2" => GameResult::Draw,
+            _ => GameResult::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameResult::WhiteWins => write!(f, "1-0"),
+            GameResult::BlackWins => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+            GameResult::Unknown => write!(f, "*"),
+        }
+    }
+}
+
+/// A time control in seconds, as written in the `TimeControl` header, e.g. `600+5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: i32,
+    pub increment: i32,
+}
+
+impl TimeControl {
+    /// The most a player can have on their clock: the base time plus one increment.
+    /// Normalized clock values are a percentage of this.
+    pub fn starting_clock(&self) -> i32 {
+        self.base + self.increment
+    }
+
+    /// Expected length of the game for one player, the way lichess estimates it (40 moves).
+    pub fn estimated_duration(&self) -> i32 {
+        self.base + 40 * self.increment
+    }
+
+    pub fn speed(&self) -> Speed {
+        match self.estimated_duration() {
+            ..=29 => Speed::UltraBullet,
+            30..=179 => Speed::Bullet,
+            180..=479 => Speed::Blitz,
+            480..=1499 => Speed::Rapid,
+            _ => Speed::Classical,
+        }
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, increment) = s
+            .split_once('+')
+            .ok_or_else(|| format!("Invalid time control: {}", s))?;
+        let time_control = TimeControl {
+            base: base.parse()?,
+            increment: increment.parse()?,
+        };
+        // clocks are kept in milliseconds, so a whole game's worth of time has to fit in an i32
+        let fits = time_control.base >= 0
+            && time_control.increment >= 0
+            && time_control
+                .increment
+                .checked_mul(40)
+                .and_then(|increments| increments.checked_add(time_control.base))
+                .and_then(|seconds| seconds.checked_mul(1000))
+                .is_some();
+        if !fits {
+            return Err(format!("Time control out of range: {}", s).into());
+        }
+        Ok(time_control)
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{}", self.base, self.increment)
+    }
+}
+
+/// Lichess speed categories, based on the estimated duration of a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    UltraBullet,
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+}
+
+impl FromStr for Speed {
+    type Err = Box<dyn std::error::Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ultrabullet" => Ok(Speed::UltraBullet),
+            "bullet" => Ok(Speed::Bullet),
+            "blitz" => Ok(Speed::Blitz),
+            "rapid" => Ok(Speed::Rapid),
+            "classical" => Ok(Speed::Classical),
+            _ => Err(format!("Invalid speed: {}", s).into()),
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Speed::UltraBullet => write!(f, "UltraBullet"),
+            Speed::Bullet => write!(f, "Bullet"),
+            Speed::Blitz => write!(f, "Blitz"),
+            Speed::Rapid => write!(f, "Rapid"),
+            Speed::Classical => write!(f, "Classical"),
+        }
+    }
+}
+
+/// Part of the game, going by the material left on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::Opening, Phase::Middlegame, Phase::Endgame];
+
+    /// The phase for this many knights, bishops, rooks and queens on the board (both sides, 14 at the start).
+    /// Same cutoffs as lichess uses: the middlegame starts at 10 pieces and the endgame at 6.
+    pub fn from_pieces(pieces: u8) -> Phase {
+        match pieces {
+            11.. => Phase::Opening,
+            7..=10 => Phase::Middlegame,
+            _ => Phase::Endgame,
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Opening => write!(f, "opening"),
+            Phase::Middlegame => write!(f, "middlegame"),
+            Phase::Endgame => write!(f, "endgame"),
+        }
+    }
+}
+
+/// What kind of move it was and what the position looked like, from replaying the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MoveContext {
+    pub capture: bool,
+    /// The move gave check (or mate).
+    pub check: bool,
+    pub promotion: bool,
+    pub castle: bool,
+    /// How many legal moves the player had to choose from.
+    /// `None` when the game couldn't be replayed, like a variant or an illegal move.
+    pub legal_moves: Option<u16>,
+    /// Knights, bishops, rooks and queens on the board before the move, `None` like `legal_moves`.
+    pub pieces: Option<u8>,
+}
+
+impl MoveContext {
+    pub fn phase(&self) -> Option<Phase> {
+        self.pieces.map(Phase::from_pieces)
+    }
+}
+
+/// Everything we know about a single move. Clock values are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveRecord {
+    /// Index of the game in the input, counting games that were skipped.
+    pub game_id: usize,
+    pub color: Color,
+    /// Half-move number, starting at 1 for white's first move.
+    pub ply: u16,
+    /// The player's clock after their previous move.
+    pub clock_before: i32,
+    /// The player's clock after this move (this is the "time left" we plot against).
+    pub clock_after: i32,
+    pub time_spent: i32,
+    /// In seconds, like the time control.
+    pub increment: i32,
+    pub white_elo: Option<i32>,
+    pub black_elo: Option<i32>,
+    pub result: GameResult,
+    pub time_control: TimeControl,
+    /// Engine evaluation before and after the move, if the game was analysed.
+    pub eval_before: Option<Eval>,
+    pub eval: Option<Eval>,
+    pub context: MoveContext,
+    /// Which of the tracked players (`ReaderConfig::players`) made the move, if any.
+    pub player: Option<u16>,
+}
+
+impl MoveRecord {
+    /// Rating of the player who made the move.
+    pub fn rating(&self) -> Option<i32> {
+        match self.color {
+            Color::White => self.white_elo,
+            Color::Black => self.black_elo,
+        }
+    }
+
+    /// The player's own move number, 1 for both white's and black's first move.
+    pub fn move_number(&self) -> u16 {
+        self.ply.div_ceil(2)
+    }
+
+    /// How many centipawns the move gave away, from the mover's point of view. Never negative.
+    pub fn centipawn_loss(&self) -> Option<i32> {
+        let change = self.eval?.centipawns() - self.eval_before?.centipawns();
+        let loss = match self.color {
+            Color::White => -change,
+            Color::Black => change,
+        };
+        Some(loss.max(0))
+    }
+}
+
+#[cfg(test)]
+impl MoveRecord {
+    /// White's first move of a 60+0 game, played instantly, with nothing else known.
+    /// Tests fill in the fields they care about with `..MoveRecord::example()`.
+    pub(crate) fn example() -> MoveRecord {
+        MoveRecord {
+            game_id: 0,
+            color: Color::White,
+            ply: 1,
+            clock_before: 60_000,
+            clock_after: 60_000,
+            time_spent: 0,
+            increment: 0,
+            white_elo: None,
+            black_elo: None,
+            result: GameResult::Unknown,
+            time_control: TimeControl {
+                base: 60,
+                increment: 0,
+            },
+            eval_before: None,
+            eval: None,
+            context: MoveContext::default(),
+            player: None,
+        }
+    }
+}
+
+/// Column-oriented storage of `MoveRecord`s.
+/// Tens of millions of moves are a lot easier on memory and cache this way.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MoveStore {
+    pub game_id: Vec<usize>,
+    pub color: Vec<Color>,
+    pub ply: Vec<u16>,
+    pub clock_before: Vec<i32>,
+    pub clock_after: Vec<i32>,
+    pub time_spent: Vec<i32>,
+    pub increment: Vec<i32>,
+    pub white_elo: Vec<Option<i32>>,
+    pub black_elo: Vec<Option<i32>>,
+    pub result: Vec<GameResult>,
+    pub time_control: Vec<TimeControl>,
+    pub eval_before: Vec<Option<Eval>>,
+    pub eval: Vec<Option<Eval>>,
+    pub context: Vec<MoveContext>,
+    pub player: Vec<Option<u16>>,
+}
+
+impl MoveStore {
+    pub fn new() -> MoveStore {
+        MoveStore::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.game_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.game_id.is_empty()
+    }
+
+    pub fn push(&mut self, record: MoveRecord) {
+        self.game_id.push(record.game_id);
+        self.color.push(record.color);
+        self.ply.push(record.ply);
+        self.clock_before.push(record.clock_before);
+        self.clock_after.push(record.clock_after);
+        self.time_spent.push(record.time_spent);
+        self.increment.push(record.increment);
+        self.white_elo.push(record.white_elo);
+        self.black_elo.push(record.black_elo);
+        self.result.push(record.result);
+        self.time_control.push(record.time_control);
+        self.eval_before.push(record.eval_before);
+        self.eval.push(record.eval);
+        self.context.push(record.context);
+        self.player.push(record.player);
+    }
+
+    pub fn get(&self, i: usize) -> MoveRecord {
+        MoveRecord {
+            game_id: self.game_id[i],
+            color: self.color[i],
+            ply: self.ply[i],
+            clock_before: self.clock_before[i],
+            clock_after: self.clock_after[i],
+            time_spent: self.time_spent[i],
+            increment: self.increment[i],
+            white_elo: self.white_elo[i],
+            black_elo: self.black_elo[i],
+            result: self.result[i],
+            time_control: self.time_control[i],
+            eval_before: self.eval_before[i],
+            eval: self.eval[i],
+            context: self.context[i],
+            player: self.player[i],
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = MoveRecord> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    /// A copy holding only the moves `keep` returns true for.
+    pub fn filtered(&self, keep: impl Fn(&MoveRecord) -> bool) -> MoveStore {
+        let mut store = MoveStore::new();
+        for record in self.iter().filter(|record| keep(record)) {
+            store.push(record);
+        }
+        store
+    }
+
+    /// Drop every move after the first `len`.
+    pub fn truncate(&mut self, len: usize) {
+        self.game_id.truncate(len);
+        self.color.truncate(len);
+        self.ply.truncate(len);
+        self.clock_before.truncate(len);
+        self.clock_after.truncate(len);
+        self.time_spent.truncate(len);
+        self.increment.truncate(len);
+        self.white_elo.truncate(len);
+        self.black_elo.truncate(len);
+        self.result.truncate(len);
+        self.time_control.truncate(len);
+        self.eval_before.truncate(len);
+        self.eval.truncate(len);
+        self.context.truncate(len);
+        self.player.truncate(len);
+    }
+
+    /// Append another store, shifting its game ids by `game_offset`.
+    pub fn append(&mut self, other: MoveStore, game_offset: usize) {
+        self.game_id
+            .extend(other.game_id.into_iter().map(|id| id + game_offset));
+        self.color.extend(other.color);
+        self.ply.extend(other.ply);
+        self.clock_before.extend(other.clock_before);
+        self.clock_after.extend(other.clock_after);
+        self.time_spent.extend(other.time_spent);
+        self.increment.extend(other.increment);
+        self.white_elo.extend(other.white_elo);
+        self.black_elo.extend(other.black_elo);
+        self.result.extend(other.result);
+        self.time_control.extend(other.time_control);
+        self.eval_before.extend(other.eval_before);
+        self.eval.extend(other.eval);
+        self.context.extend(other.context);
+        self.player.extend(other.player);
+    }
+
+    /// Time taken for every move, bucketed by the (whole) time left on the clock.
+    /// `max_time` is the largest bucket, see `xy` for `normalize`.
+    pub fn time_data(&self, max_time: i32, normalize: bool) -> Vec<Vec<f64>> {
+        let (_, y_values) = self.xy(normalize);
+        self.buckets(max_time, normalize, y_values.into_iter().map(Some))
+    }
+
+    /// How good every move with evaluations was, bucketed the same way as `time_data`.
+    pub fn judgement_data(
+        &self,
+        max_time: i32,
+        normalize: bool,
+        method: JudgementMethod,
+    ) -> Vec<Vec<Judgement>> {
+        self.buckets(
+            max_time,
+            normalize,
+            self.iter().map(|record| judge(&record, method)),
+        )
+    }
+
+    // put one value per move into the bucket for the time left after it. `None`s are left out
+    fn buckets<T>(
+        &self,
+        max_time: i32,
+        normalize: bool,
+        values: impl Iterator<Item = Option<T>>,
+    ) -> Vec<Vec<T>> {
+        let mut buckets: Vec<Vec<T>> = (0..=max_time).map(|_| Vec::new()).collect();
+        let (x_values, _) = self.xy(normalize);
+        for (x, value) in x_values.into_iter().zip(values) {
+            if let Some(value) = value {
+                buckets[(x as usize).min(max_time as usize)].push(value);
+            }
+        }
+        buckets
+    }
+
+    /// Time left and time taken for every move, ready for regression.
+    /// In seconds, or when `normalize` is set, as a percentage of the game's starting clock
+    /// so games with different time controls can be pooled.
+    pub fn xy(&self, normalize: bool) -> (Vec<f64>, Vec<f64>) {
+        self.clock_after
+            .iter()
+            .zip(&self.time_spent)
+            .zip(&self.time_control)
+            .map(|((&x, &y), tc)| {
+                // clocks are stored in milliseconds
+                let divisor = if normalize {
+                    tc.starting_clock() as f64 * 10.0
+                } else {
+                    1000.0
+                };
+                (x as f64 / divisor, y as f64 / divisor)
+            })
+            .unzip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, GameResult, MoveRecord, MoveStore, Speed, TimeControl};
+    use crate::annotations::Eval;
+
+    fn record(game_id: usize, clock_after: i32, time_spent: i32) -> MoveRecord {
+        MoveRecord {
+            game_id,
+            ply: 3,
+            clock_before: (clock_after + time_spent) * 1000,
+            clock_after: clock_after * 1000,
+            time_spent: time_spent * 1000,
+            white_elo: Some(1500),
+            result: GameResult::Draw,
+            ..MoveRecord::example()
+        }
+    }
+
+    #[test]
+    fn time_control() {
+        let tc: TimeControl = "600+5".parse().unwrap();
+        assert_eq!(
+            tc,
+            TimeControl {
+                base: 600,
+                increment: 5
+            }
+        );
+        assert_eq!(tc.to_string(), "600+5");
+        assert!("-".parse::<TimeControl>().is_err());
+        assert!("-60+0".parse::<TimeControl>().is_err());
+        assert!("3000000+0".parse::<TimeControl>().is_err());
+        assert!("0+60000".parse::<TimeControl>().is_err());
+    }
+
+    #[test]
+    fn speed() {
+        let speed = |tc: &str| tc.parse::<TimeControl>().unwrap().speed();
+        assert_eq!(speed("15+0"), Speed::UltraBullet);
+        assert_eq!(speed("60+0"), Speed::Bullet);
+        // 120 + 40 * 1 = 160
+        assert_eq!(speed("120+1"), Speed::Bullet);
+        assert_eq!(speed("120+2"), Speed::Blitz);
+        assert_eq!(speed("300+3"), Speed::Blitz);
+        assert_eq!(speed("600+0"), Speed::Rapid);
+        assert_eq!(speed("900+10"), Speed::Rapid);
+        assert_eq!(speed("1800+0"), Speed::Classical);
+        assert_eq!("Blitz".parse::<Speed>().unwrap(), Speed::Blitz);
+        assert!("hyperbullet".parse::<Speed>().is_err());
+    }
+
+    #[test]
+    fn store() {
+        let mut store = MoveStore::new();
+        store.push(record(0, 50, 3));
+        store.push(record(0, 50, 7));
+        store.push(record(1, 2, 1));
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get(1), record(0, 50, 7));
+
+        let time_data = store.time_data(60, false);
+        assert_eq!(time_data.len(), 61);
+        assert_eq!(time_data[50], vec![3.0, 7.0]);
+        assert_eq!(time_data[2], vec![1.0]);
+
+        // 50 of 60 seconds is 83.3%
+        let time_data = store.time_data(100, true);
+        assert_eq!(time_data[83], vec![5.0, 7000.0 / 600.0]);
+
+        let mut other = MoveStore::new();
+        other.push(record(0, 10, 2));
+        store.append(other, 5);
+        assert_eq!(store.game_id, vec![0, 0, 1, 5]);
+        assert_eq!(store.xy(false).0, vec![50.0, 50.0, 2.0, 10.0]);
+
+        let game_0 = store.filtered(|m| m.game_id == 0);
+        assert_eq!(game_0.time_spent, vec![3000, 7000]);
+
+        store.truncate(1);
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.get(0), record(0, 50, 3));
+    }
+
+    #[test]
+    fn centipawn_loss() {
+        let mut white = record(0, 50, 3);
+        assert_eq!(white.centipawn_loss(), None);
+        white.eval_before = Some(Eval::Centipawns(30));
+        white.eval = Some(Eval::Centipawns(-120));
+        assert_eq!(white.centipawn_loss(), Some(150));
+
+        // the same change is a gain for black
+        let mut black = white;
+        black.color = Color::Black;
+        assert_eq!(black.centipawn_loss(), Some(0));
+        // missing a mate in a won position costs at most the cap
+        black.eval_before = Some(Eval::Mate(-2));
+        black.eval = Some(Eval::Centipawns(-400));
+        assert_eq!(black.centipawn_loss(), Some(600));
+    }
+}
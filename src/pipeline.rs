@@ -0,0 +1,357 @@
+// Parallel ingestion pipeline
+// one thread splits the PGN into chunks of whole games, a pool of workers runs its own GameReader
+// over each chunk, and the main thread merges the results back together in file order.
+// merging in order is what keeps the output identical to a single-threaded run.
+
+use crate::reader::GameReader;
+use pgn_reader::BufferedReader;
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead},
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+/// Number of games handed to a worker at a time.
+/// 4096 lichess games is only a few megabytes, so a handful of these in flight is cheap.
+pub const GAMES_PER_CHUNK: usize = 4096;
+
+/// Read every game from `input` into `game_reader`, using `threads` workers.
+/// With a single thread this is just `BufferedReader::read_all`.
+pub fn read_games<R: BufRead + Send>(
+    input: R,
+    game_reader: &mut GameReader,
+    threads: usize,
+) -> io::Result<()> {
+    if threads <= 1 {
+        return BufferedReader::new(input).read_all(game_reader);
+    }
+    read_games_parallel(input, game_reader, threads, GAMES_PER_CHUNK)
+}
+
+fn read_games_parallel<R: BufRead + Send>(
+    input: R,
+    game_reader: &mut GameReader,
+    threads: usize,
+    games_per_chunk: usize,
+) -> io::Result<()> {
+    // workers never stop early. the game limit only makes sense in file order,
+    // so it gets applied while merging instead
+    let mut worker_config = game_reader.config.clone();
+    // the chunks are only needed after they're read if the limit falls inside one
+    let keep_chunks = worker_config.max_games.take().is_some();
+
+    // the splitter needs a slot to hand out a chunk, and only gets it back once that chunk is merged.
+    // otherwise one slow chunk lets the others pile up waiting for their turn, and the splitter
+    // could load the whole file into memory
+    let slots = threads * 2;
+    let (slot_tx, slot_rx) = mpsc::sync_channel::<()>(slots);
+    for _ in 0..slots {
+        slot_tx.send(()).unwrap();
+    }
+    let (chunk_tx, chunk_rx) = mpsc::channel::<(usize, Vec<u8>)>();
+    let chunk_rx = Mutex::new(chunk_rx);
+    let (result_tx, result_rx) = mpsc::channel::<ChunkResult>();
+
+    thread::scope(|s| {
+        let splitter = s.spawn(move || -> io::Result<()> {
+            let mut chunks = ChunkSplitter::new(input, games_per_chunk);
+            let mut index = 0;
+            // the slots only run out for good if the merging stopped, nothing to do then
+            while slot_rx.recv().is_ok() {
+                let Some(chunk) = chunks.next_chunk()? else {
+                    break;
+                };
+                // the receiving end only goes away if every worker died, nothing to do then
+                if chunk_tx.send((index, chunk)).is_err() {
+                    break;
+                }
+                index += 1;
+            }
+            Ok(())
+        });
+
+        for _ in 0..threads {
+            let chunk_rx = &chunk_rx;
+            let result_tx = result_tx.clone();
+            let worker_config = &worker_config;
+            s.spawn(move || loop {
+                // hold the lock only long enough to grab the next chunk
+                let next = chunk_rx.lock().unwrap().recv();
+                let Ok((index, chunk)) = next else {
+                    break;
+                };
+                let mut part = GameReader::new(worker_config.clone());
+                let read = BufferedReader::new(&chunk[..]).read_all(&mut part);
+                let chunk = keep_chunks.then_some(chunk);
+                if result_tx.send((index, chunk, part, read)).is_err() {
+                    break;
+                }
+            });
+        }
+        // otherwise the loop below would wait forever
+        drop(result_tx);
+
+        // dropped if merging fails, which lets the splitter stop
+        let slot_tx = slot_tx;
+        // chunks finish out of order, so park them until it's their turn
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (index, chunk, part, read) in result_rx {
+            pending.insert(index, (chunk, part, read));
+            while let Some((chunk, part, read)) = pending.remove(&next) {
+                // the games read before the error still count, like they would reading the file in one go
+                merge_chunk(game_reader, chunk, part)?;
+                read?;
+                next += 1;
+                // the splitter may be done already
+                let _ = slot_tx.send(());
+            }
+        }
+
+        splitter.join().expect("PGN splitter thread panicked")
+    })
+}
+
+// a chunk's index, its bytes if they're needed again, what was read from it and whether that went ok
+type ChunkResult = (usize, Option<Vec<u8>>, GameReader, io::Result<()>);
+
+/// Merge one chunk's results into the running total, respecting `--max-games`.
+fn merge_chunk(
+    game_reader: &mut GameReader,
+    chunk: Option<Vec<u8>>,
+    part: GameReader,
+) -> io::Result<()> {
+    let (Some(max_games), Some(chunk)) = (game_reader.config.max_games, chunk) else {
+        game_reader.merge(part);
+        return Ok(());
+    };
+    if game_reader.games_analyzed >= max_games {
+        // already full, these games only count towards the total
+        game_reader.total_games += part.total_games;
+    } else if game_reader.games_analyzed + part.games_analyzed > max_games {
+        // the limit falls somewhere inside this chunk.
+        // re-read it with whatever is left of the budget so only the first few games count.
+        // this happens at most once per run so it's cheap
+        let mut limited_config = game_reader.config.clone();
+        limited_config.max_games = Some(max_games - game_reader.games_analyzed);
+        let mut limited = GameReader::new(limited_config);
+        BufferedReader::new(&chunk[..]).read_all(&mut limited)?;
+        game_reader.merge(limited);
+    } else {
+        game_reader.merge(part);
+    }
+    Ok(())
+}
+
+/// Splits a PGN stream into chunks that each hold a whole number of games.
+/// A new game starts at the first tag pair line that follows some movetext.
+/// Lines inside `{...}` comments never count, movetext wrapped onto a line starting with
+/// `[%emt ...]` or the like is still part of the same game.
+struct ChunkSplitter<R> {
+    input: R,
+    games_per_chunk: usize,
+    // first line of the next chunk, read while looking for the end of the current one
+    carry: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> ChunkSplitter<R> {
+    fn new(input: R, games_per_chunk: usize) -> Self {
+        ChunkSplitter {
+            input,
+            games_per_chunk,
+            carry: Vec::new(),
+            done: false,
+        }
+    }
+
+    fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut chunk = std::mem::take(&mut self.carry);
+        let mut games = 0;
+        let mut in_movetext = false;
+        let mut in_comment = false;
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if self.input.read_until(b'\n', &mut line)? == 0 {
+                self.done = true;
+                break;
+            }
+            let trimmed = line.trim_ascii_start();
+            if !in_comment && is_tag_pair(trimmed) {
+                if in_movetext {
+                    // previous game is over
+                    games += 1;
+                    in_movetext = false;
+                    if games == self.games_per_chunk {
+                        self.carry.extend_from_slice(&line);
+                        break;
+                    }
+                }
+            } else if !trimmed.is_empty() {
+                in_movetext = true;
+                in_comment = ends_in_comment(trimmed, in_comment);
+            }
+            chunk.extend_from_slice(&line);
+        }
+
+        if chunk.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        Ok(Some(chunk))
+    }
+}
+
+// `[Name "value"]`, as opposed to movetext that happens to start with a `[%clk ...]` command
+fn is_tag_pair(line: &[u8]) -> bool {
+    let Some(rest) = line.strip_prefix(b"[") else {
+        return false;
+    };
+    let name_length = rest
+        .iter()
+        .take_while(|c| c.is_ascii_alphanumeric() || **c == b'_')
+        .count();
+    if name_length == 0 || !rest[0].is_ascii_alphabetic() {
+        return false;
+    }
+    rest[name_length..].trim_ascii_start().starts_with(b"\"")
+}
+
+// whether a `{...}` comment is still open at the end of this movetext line
+fn ends_in_comment(line: &[u8], mut in_comment: bool) -> bool {
+    for c in line {
+        match (in_comment, c) {
+            (false, b'{') => in_comment = true,
+            (true, b'}') => in_comment = false,
+            // rest of the line is a comment, braces and all
+            (false, b';') => break,
+            _ => {}
+        }
+    }
+    in_comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_tag_pair, read_games_parallel, ChunkSplitter};
+    use crate::reader::{GameReader, ReaderConfig};
+    use pgn_reader::BufferedReader;
+
+    // a handful of fake lichess games with different ratings and clocks
+    fn sample_pgn() -> String {
+        let mut pgn = String::new();
+        for game in 0..23 {
+            let elo = 1000 + game * 50;
+            let time_control = if game % 5 == 0 { "180+0" } else { "60+0" };
+            pgn.push_str(&format!(
+                "[Event \"Rated Bullet game\"]\n[WhiteElo \"{elo}\"]\n[BlackElo \"{elo}\"]\n[TimeControl \"{time_control}\"]\n\n"
+            ));
+            let mut clocks = [60, 60];
+            for ply in 0..(10 + game % 7) {
+                let side = ply % 2;
+                clocks[side] -= (game + ply) % 4;
+                let prefix = if side == 0 {
+                    format!("{}. ", ply / 2 + 1)
+                } else {
+                    format!("{}... ", ply / 2 + 1)
+                };
+                pgn.push_str(&format!(
+                    "{prefix}e4 {{ [%clk 0:00:{:02}] }} ",
+                    clocks[side]
+                ));
+            }
+            pgn.push_str("1-0\n\n");
+        }
+        pgn
+    }
+
+    fn config(max_games: Option<usize>) -> ReaderConfig {
+        let mut config = ReaderConfig::new("60+0".parse().unwrap());
+        config.max_games = max_games;
+        config
+    }
+
+    fn assert_same(a: &GameReader, b: &GameReader) {
+        assert_eq!(a.moves, b.moves);
+        assert_eq!(a.time_data(), b.time_data());
+        assert_eq!(a.games_analyzed, b.games_analyzed);
+        assert_eq!(a.total_games, b.total_games);
+        assert_eq!(a.moves_analyzed, b.moves_analyzed);
+    }
+
+    #[test]
+    fn chunks_are_game_aligned() {
+        let pgn = sample_pgn();
+        let mut splitter = ChunkSplitter::new(pgn.as_bytes(), 5);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = splitter.next_chunk().unwrap() {
+            chunks.push(chunk);
+        }
+        // 23 games -> 4 full chunks and one with the leftovers
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|c| c.starts_with(b"[Event")));
+        assert_eq!(chunks.concat(), pgn.as_bytes());
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let pgn = sample_pgn();
+        for max_games in [None, Some(7), Some(10)] {
+            let mut sequential = GameReader::new(config(max_games));
+            BufferedReader::new(pgn.as_bytes())
+                .read_all(&mut sequential)
+                .unwrap();
+
+            for (threads, games_per_chunk) in [(2, 1), (3, 4), (4, 5), (8, 100)] {
+                let mut parallel = GameReader::new(config(max_games));
+                read_games_parallel(pgn.as_bytes(), &mut parallel, threads, games_per_chunk)
+                    .unwrap();
+                assert_same(&sequential, &parallel);
+            }
+        }
+    }
+
+    #[test]
+    fn read_errors() {
+        // pgn-reader gives up on a comment that doesn't fit in its buffer,
+        // so the games after this one never get read
+        let mut pgn = sample_pgn();
+        pgn.push_str(&format!(
+            "[TimeControl \"60+0\"]\n\n1. e4 {{ {} }} 1-0\n\n",
+            "x".repeat(100_000)
+        ));
+        pgn.push_str(&sample_pgn());
+
+        let mut sequential = GameReader::new(config(None));
+        assert!(BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut sequential)
+            .is_err());
+        let mut parallel = GameReader::new(config(None));
+        assert!(read_games_parallel(pgn.as_bytes(), &mut parallel, 3, 4).is_err());
+        assert_same(&sequential, &parallel);
+    }
+
+    #[test]
+    fn wrapped_comments() {
+        // OTB style games with their comments wrapped, some lines start with a command
+        let game = "[Event \"Club match\"]\n[TimeControl \"60+0\"]\n\n1. e4 { [%clk 0:01:00]\n[%emt 0:00:05]} e5 {\n[%clk 0:00:58] } 2. Nf3 {\n[Event \"not a tag\"] } 1-0\n\n";
+        let pgn = game.repeat(3);
+        let mut splitter = ChunkSplitter::new(pgn.as_bytes(), 1);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = splitter.next_chunk().unwrap() {
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c == game.as_bytes()));
+
+        assert!(is_tag_pair(b"[WhiteElo \"1500\"]"));
+        assert!(is_tag_pair(b"[Site\"x\"]"));
+        assert!(!is_tag_pair(b"[%emt 0:00:05]}"));
+        assert!(!is_tag_pair(b"[%clk 0:01:00] 2. d4"));
+        assert!(!is_tag_pair(b"1. e4"));
+    }
+}
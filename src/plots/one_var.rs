@@ -5,10 +5,11 @@ use plotters::{coord::Shift, prelude::*};
 
 use std::error::Error;
 
-use super::plotter::generate_caption;
+use super::plotter::{generate_caption, PlotOptions};
 
 pub fn generate_one_var_plots(
     game_reader: &GameReader,
+    options: &PlotOptions,
     path: &std::path::Path,
     resolution: (u32, u32),
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -23,7 +24,7 @@ pub fn generate_one_var_plots(
             .into_drawing_area(),
         game_reader,
     )?;
-    if game_reader.args.svg {
+    if options.svg {
         x_histogram(
             SVGBackend::new(&path.join("1-var").join("x-histogram.svg"), resolution)
                 .into_drawing_area(),
@@ -50,18 +51,17 @@ where
 {
     // ----- DATA -----
     let num_buckets = 10;
-    let bucket_size = game_reader.max_allowed_time as f32 / num_buckets as f32;
-    let max_x = game_reader.max_allowed_time as f32 + bucket_size;
-    let sum = game_reader
-        .time_data
-        .iter()
-        .flatten()
-        .collect::<Vec<&i32>>()
-        .len();
-    let data = game_reader.time_data.iter().enumerate().map(|(i, x)| {
-        let num = x.len() as f32 / sum as f32;
-        (i as f32, num)
-    });
+    let bucket_size = game_reader.max_clock() as f32 / num_buckets as f32;
+    let max_x = game_reader.max_clock() as f32 + bucket_size;
+    let sum = game_reader.moves.len();
+    let data = game_reader
+        .time_data()
+        .into_iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let num = x.len() as f32 / sum as f32;
+            (i as f32, num)
+        });
     // ----- chart stuff!! -----
     root.fill(&WHITE)?;
     let mut chart = ChartBuilder::on(&root)
@@ -78,7 +78,7 @@ where
         .disable_x_mesh()
         .bold_line_style(WHITE.mix(0.3))
         .y_desc("Relative Frequency of Moves Made (percent)")
-        .x_desc("Time left (S)")
+        .x_desc(format!("Time left ({})", game_reader.clock_unit()))
         .axis_desc_style(("sans-serif", 15))
         .draw()?;
     chart.draw_series(
@@ -102,18 +102,14 @@ where
 {
     // ----- DATA -----
 
-    let all_moves = game_reader
-        .time_data
-        .iter()
-        .flatten()
-        .collect::<Vec<&i32>>();
+    let (_, all_moves) = game_reader.xy();
 
     let total = all_moves.len();
     let num_buckets = 10;
-    let max_x = game_reader.max_allowed_time as f32 / 10.0;
+    let max_x = game_reader.max_clock() as f32 / 10.0;
     let bucket_size = max_x / num_buckets as f32;
 
-    let data = all_moves.iter().map(|v| (**v as f32, 1f32 / total as f32));
+    let data = all_moves.iter().map(|v| (*v as f32, 1f32 / total as f32));
 
     let mut chart = ChartBuilder::on(&root)
         .x_label_area_size(35)
@@ -131,7 +127,7 @@ where
         .disable_x_mesh()
         .bold_line_style(WHITE.mix(0.3))
         .y_desc("Relative Frequency of Moves Made (percent)")
-        .x_desc("Time Taken to Move (S)")
+        .x_desc(format!("Time Taken to Move ({})", game_reader.clock_unit()))
         .axis_desc_style(("sans-serif", 15))
         .draw()?;
     chart.draw_series(
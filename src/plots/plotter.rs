@@ -1,6 +1,8 @@
 use super::two_var::generate_two_var_plots;
 use crate::plots::one_var::generate_one_var_plots;
+use crate::quality::JudgementMethod;
 use crate::reader::GameReader;
+use crate::regression::{pick_model, Criterion, ModelKind};
 use std::{
     fs::create_dir,
     path::PathBuf,
@@ -9,19 +11,82 @@ use std::{
 
 // TODO: add helpful error messages
 
-pub fn generate_plots(game_reader: &GameReader) -> Result<(), Box<dyn std::error::Error>> {
+/// Which graphs to draw and where to put them.
+#[derive(Debug, Clone, Default)]
+pub struct PlotOptions {
+    /// Creates a directory titled with the UNIX timestamp in this directory.
+    pub output: String,
+    /// Width and height of the images in pixels. Default is 1000.
+    pub resolution: Option<i32>,
+    /// Scatterplot of every move.
+    pub all: bool,
+    /// Line graph of average TTM.
+    pub averages: bool,
+    /// Separate average lines for white and black.
+    pub by_color: bool,
+    /// Line graph of average TTM and time left by move number.
+    pub by_move: bool,
+    /// Moves past this move number are counted with it.
+    pub max_move: u16,
+    /// Line graph of inaccuracy, mistake and blunder rates.
+    pub errors: bool,
+    pub judgement: JudgementMethod,
+    pub residuals: bool,
+    /// Histograms of time left and time taken.
+    pub one_var: bool,
+    /// Draw the regression curve over the averages and all TTMs graphs.
+    pub overlay_regression: bool,
+    /// The model drawn by `overlay_regression` and used for the residuals.
+    /// `None` picks the best of `regression::CANDIDATES`, once for all the graphs.
+    pub model: Option<ModelKind>,
+    pub rank_by: Criterion,
+    /// Folds for cross-validating the candidates.
+    pub folds: usize,
+    /// Quantile regression curves on the averages graph.
+    pub quantile_fan: bool,
+    /// Percentiles of time taken for `quantile_fan`, e.g. 10, 50 and 90.
+    pub quantiles: Vec<f64>,
+    /// Shade confidence and prediction bands around the regression curve.
+    pub bands: bool,
+    /// Confidence level of the bands, e.g. 0.95.
+    pub confidence: f64,
+    /// Output SVG files in addition to PNG.
+    pub svg: bool,
+}
+
+pub fn generate_plots(
+    game_reader: &GameReader,
+    options: &PlotOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
     let resolution = {
-        if let Some(r) = game_reader.args.resolution {
+        if let Some(r) = options.resolution {
             (r as u32, r as u32)
         } else {
             (1000, 1000)
         }
     };
-    let path = gen_path(&game_reader.args.output)?;
-    generate_two_var_plots(game_reader, &path, resolution)?;
-    if game_reader.args.one_var {
+    // ranking the candidates means cross-validating all of them, so it's done once here
+    // instead of for every graph (and every SVG copy) that draws a curve
+    let mut options = options.clone();
+    if options.model.is_none()
+        && (options.overlay_regression || options.quantile_fan || options.residuals)
+    {
+        let (x_values, y_values) = game_reader.xy();
+        options.model = Some(pick_model(
+            None,
+            &game_reader.moves.game_id,
+            &x_values,
+            &y_values,
+            options.folds,
+            options.rank_by,
+        )?);
+    }
+    let options = &options;
+    let path = gen_path(&options.output)?;
+    generate_two_var_plots(game_reader, options, &path, resolution)?;
+    if options.one_var {
         println!("Creating one-variable histograms...");
-        generate_one_var_plots(game_reader, &path, resolution)?;
+        generate_one_var_plots(game_reader, options, &path, resolution)?;
     }
     Ok(())
 }
@@ -54,35 +119,25 @@ pub enum GraphType {
     RelativeFrequencyX,
     RelativeFrequencyY,
     Residuals,
+    ErrorRates,
+    MoveNumber,
 }
 
 pub fn generate_caption(graph_type: GraphType, game_reader: &GameReader) -> String {
-    let elo_text = {
-        if game_reader.args.min_rating.is_none() && game_reader.args.max_rating.is_none() {
-            "No ELO Limit".to_string()
-        } else {
-            let mut str = String::new();
-            if let Some(rating) = game_reader.args.min_rating {
-                str.push_str(&rating.to_string());
-            }
-            str.push('-');
-            if let Some(rating) = game_reader.args.max_rating {
-                str.push_str(&rating.to_string());
-            };
-            str.push_str(" ELO*");
-            str
-        }
-    };
     let title = match graph_type {
         GraphType::All => "All",
         GraphType::Average => "Average TTM",
         GraphType::RelativeFrequencyX => "RF of Time Left",
         GraphType::RelativeFrequencyY => "RF of TTM",
         GraphType::Residuals => "Residuals",
+        GraphType::ErrorRates => "Error Rates",
+        GraphType::MoveNumber => "Average TTM by Move Number",
     };
     format!(
-        "{} ({}, {} seconds, {} Games)",
-        title, elo_text, game_reader.args.time_control, game_reader.games_analyzed
+        "{} ({}, {} Games)",
+        title,
+        game_reader.config.filter.description(),
+        game_reader.games_analyzed
     )
 }
 
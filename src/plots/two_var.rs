@@ -1,6 +1,12 @@
-use super::plotter::{generate_caption, GraphType};
-use crate::analysis::{generate_residuals, quadratic_regression};
+use super::plotter::{generate_caption, GraphType, PlotOptions};
+use crate::inference::Inference;
+use crate::moves;
+use crate::quality::{error_rates as error_rates_by_bucket, Judgement};
+use crate::quantile::{ordinal, QuantileModel};
 use crate::reader::GameReader;
+use crate::regression::{pick_model, RegressionModel};
+use crate::usage;
+use plotters::coord::types::RangedCoordf32;
 use plotters::drawing::DrawingArea;
 use plotters::{coord::Shift, prelude::*};
 use std::error::Error;
@@ -9,55 +15,96 @@ use std::error::Error;
 // - make graphs look good
 pub fn generate_two_var_plots(
     game_reader: &GameReader,
+    options: &PlotOptions,
     path: &std::path::Path,
     resolution: (u32, u32),
 ) -> Result<(), Box<dyn std::error::Error>> {
     println!("Creating average TTM graph...");
-    if game_reader.args.averages {
+    if options.averages {
         averages(
             BitMapBackend::new(&path.join("2-var").join("ttm_averages.png"), resolution)
                 .into_drawing_area(),
             game_reader,
+            options,
             resolution,
         )?;
-        if game_reader.args.svg {
+        if options.svg {
             averages(
                 SVGBackend::new(&path.join("2-var").join("ttm_averages.svg"), resolution)
                     .into_drawing_area(),
                 game_reader,
+                options,
                 resolution,
             )?;
         }
     }
-    if game_reader.args.all {
+    if options.all {
         println!("Creating all TTMs graph...");
         all_points(
             BitMapBackend::new(&path.join("2-var").join("all_ttm.png"), resolution)
                 .into_drawing_area(),
             game_reader,
+            options,
             resolution,
         )?;
-        if game_reader.args.svg {
+        if options.svg {
             all_points(
                 SVGBackend::new(&path.join("2-var").join("all_ttm.svg"), resolution)
                     .into_drawing_area(),
                 game_reader,
+                options,
                 resolution,
             )?;
         }
     }
-    if game_reader.args.residuals {
+    if options.by_move {
+        println!("Creating move number graph...");
+        move_numbers(
+            BitMapBackend::new(&path.join("2-var").join("ttm_by_move.png"), resolution)
+                .into_drawing_area(),
+            game_reader,
+            options,
+        )?;
+        if options.svg {
+            move_numbers(
+                SVGBackend::new(&path.join("2-var").join("ttm_by_move.svg"), resolution)
+                    .into_drawing_area(),
+                game_reader,
+                options,
+            )?;
+        }
+    }
+    if options.errors {
+        println!("Creating error rates graph...");
+        error_rates(
+            BitMapBackend::new(&path.join("2-var").join("error_rates.png"), resolution)
+                .into_drawing_area(),
+            game_reader,
+            options,
+        )?;
+        if options.svg {
+            error_rates(
+                SVGBackend::new(&path.join("2-var").join("error_rates.svg"), resolution)
+                    .into_drawing_area(),
+                game_reader,
+                options,
+            )?;
+        }
+    }
+    if options.residuals {
         residuals(
             BitMapBackend::new(&path.join("2-var").join("residuals.png"), resolution)
                 .into_drawing_area(),
             game_reader,
+            options,
             resolution,
         )?;
-        if game_reader.args.svg {
+        if options.svg {
             residuals(
                 SVGBackend::new(&path.join("2-var").join("residuals.svg"), resolution)
                     .into_drawing_area(),
                 game_reader,
+                options,
                 resolution,
             )?;
         }
@@ -68,6 +115,7 @@ pub fn generate_two_var_plots(
 fn averages<T>(
     root: DrawingArea<T, Shift>,
     game_reader: &GameReader,
+    options: &PlotOptions,
     resolution: (u32, u32),
 ) -> Result<(), Box<dyn Error + 'static>>
 where
@@ -76,9 +124,9 @@ where
 {
     // ----- DATA ----- //
     let averages = game_reader
-        .time_data
+        .time_data()
         .iter()
-        .map(|y_values| y_values.iter().sum::<i32>() as f32 / y_values.len() as f32)
+        .map(|y_values| (y_values.iter().sum::<f64>() / y_values.len() as f64) as f32)
         .collect::<Vec<f32>>();
 
     // create an iterator of points to create our scatterplot
@@ -87,7 +135,7 @@ where
         RED.stroke_width(2),
     );
 
-    let max_x = game_reader.max_allowed_time as f32;
+    let max_x = game_reader.max_clock() as f32;
     let max_y = averages.into_iter().reduce(f32::max).unwrap() + 1f32;
 
     // ----- chart stuff ----- //
@@ -106,8 +154,11 @@ where
 
     chart
         .configure_mesh()
-        .y_desc("TTM (S)")
-        .x_desc("Time Left on Player Clock (S)")
+        .y_desc(format!("TTM ({})", game_reader.clock_unit()))
+        .x_desc(format!(
+            "Time Left on Player Clock ({})",
+            game_reader.clock_unit()
+        ))
         .axis_desc_style(("sans-serif", 15))
         .draw()?;
 
@@ -115,33 +166,297 @@ where
         .draw_series(average_line)?
         .label("Average time taken")
         .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 50, y)], RED.stroke_width(2)));
-    if game_reader.args.overlay_regression {
-        let (x_values, y_values): (Vec<f64>, Vec<f64>) = game_reader
-            .time_data
+    if options.by_color {
+        for (color, style) in [
+            (moves::Color::White, BLUE.stroke_width(1)),
+            (moves::Color::Black, BLACK.stroke_width(1)),
+        ] {
+            let time_data = game_reader
+                .moves
+                .filtered(|m| m.color == color)
+                .time_data(game_reader.max_clock(), game_reader.normalize);
+            chart
+                .draw_series(LineSeries::new(
+                    time_data
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, y_values)| !y_values.is_empty())
+                        .map(|(x, y_values)| {
+                            let average = y_values.iter().sum::<f64>() / y_values.len() as f64;
+                            (x as f32, average as f32)
+                        }),
+                    style,
+                ))?
+                .label(format!("Average time taken ({})", color))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 50, y)], style));
+        }
+    }
+    if options.overlay_regression {
+        overlay_regression(&mut chart, game_reader, options, max_x)?;
+    }
+    if options.quantile_fan {
+        let (x_values, y_values) = game_reader.xy();
+        let kind = pick_model(
+            options.model,
+            &game_reader.moves.game_id,
+            &x_values,
+            &y_values,
+            options.folds,
+            options.rank_by,
+        )?;
+        for percentile in &options.quantiles {
+            // like the analysis, one curve that can't be fitted doesn't stop the rest
+            let curve = match QuantileModel::fit(kind, percentile / 100.0, &x_values, &y_values) {
+                Ok(curve) => curve,
+                Err(e) => {
+                    println!("{} percentile: {}", ordinal(*percentile), e);
+                    continue;
+                }
+            };
+            // the further from the median, the fainter the line
+            let style = BLUE
+                .mix(1.0 - (percentile - 50.0).abs() / 80.0)
+                .stroke_width(2);
+            chart
+                .draw_series(LineSeries::new(regression_curve(&curve, max_x), style))?
+                .label(curve.name())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 50, y)], style));
+        }
+    }
+    chart.configure_series_labels().draw()?;
+    root.present()?;
+    Ok(())
+}
+fn move_numbers<T>(
+    root: DrawingArea<T, Shift>,
+    game_reader: &GameReader,
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error + 'static>>
+where
+    T: IntoDrawingArea,
+    <T as DrawingBackend>::ErrorType: 'static,
+{
+    // ----- DATA ----- //
+    // (move number, average time taken, average time left)
+    let averages =
+        usage::by_move_number(&game_reader.moves, game_reader.normalize, options.max_move)
             .iter()
             .enumerate()
-            .flat_map(|(x, row)| row.iter().map(move |&y| (x as f64, y as f64)))
-            .unzip();
-
-        let r = quadratic_regression(&x_values, &y_values)?;
-
-        chart.draw_series(LineSeries::new(
-            (0..max_x as usize).map(|x| {
-                (
-                    x as f32,
-                    r.0 as f32 * x as f32 * x as f32 + r.1 as f32 * x as f32 + r.2 as f32,
-                )
-            }),
-            GREEN.stroke_width(2),
-        ))?;
+            .filter(|(_, points)| !points.is_empty())
+            .map(|(move_number, points)| {
+                let n = points.len() as f64;
+                let time_left = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+                let time_taken = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+                (move_number as f32, time_taken as f32, time_left as f32)
+            })
+            .collect::<Vec<_>>();
+
+    let max_x = options.max_move.max(2) as f32;
+    let max_y = averages.iter().map(|a| a.1).fold(0f32, f32::max) + 1f32;
+    let max_clock = game_reader.max_clock() as f32;
+
+    // ----- chart stuff ----- //
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            generate_caption(GraphType::MoveNumber, game_reader),
+            ("sans-serif", 25).into_font(),
+        )
+        .margin(35)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Right, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 100)
+        .build_cartesian_2d(1f32..max_x, 0f32..max_y)?
+        .set_secondary_coord(1f32..max_x, 0f32..max_clock);
+
+    chart
+        .configure_mesh()
+        .y_desc(format!("TTM ({})", game_reader.clock_unit()))
+        .x_desc("Move Number")
+        .axis_desc_style(("sans-serif", 15))
+        .draw()?;
+    chart
+        .configure_secondary_axes()
+        .y_desc(format!(
+            "Time Left on Player Clock ({})",
+            game_reader.clock_unit()
+        ))
+        .axis_desc_style(("sans-serif", 15))
+        .draw()?;
+
+    chart
+        .draw_series(LineSeries::new(
+            averages.iter().map(|(x, y, _)| (*x, *y)),
+            RED.stroke_width(2),
+        ))?
+        .label("Average time taken")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 50, y)], RED.stroke_width(2)));
+    chart
+        .draw_secondary_series(LineSeries::new(
+            averages.iter().map(|(x, _, clock)| (*x, *clock)),
+            BLUE.stroke_width(2),
+        ))?
+        .label("Average time left")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 50, y)], BLUE.stroke_width(2)));
+    chart.configure_series_labels().draw()?;
+    root.present()?;
+    Ok(())
+}
+
+fn error_rates<T>(
+    root: DrawingArea<T, Shift>,
+    game_reader: &GameReader,
+    options: &PlotOptions,
+) -> Result<(), Box<dyn Error + 'static>>
+where
+    T: IntoDrawingArea,
+    <T as DrawingBackend>::ErrorType: 'static,
+{
+    // ----- DATA ----- //
+    let judgement_data = game_reader.judgement_data(options.judgement);
+    let rates =
+        Judgement::ERRORS.map(|judgement| error_rates_by_bucket(&judgement_data, judgement));
+
+    let max_x = game_reader.max_clock() as f32;
+    let max_y = rates
+        .iter()
+        .flatten()
+        .flatten()
+        .fold(0f64, |a, &b| a.max(b)) as f32
+        + 0.05;
+
+    // ----- chart stuff ----- //
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(
+            generate_caption(GraphType::ErrorRates, game_reader),
+            ("sans-serif", 25).into_font(),
+        )
+        .margin(35)
+        .set_label_area_size(LabelAreaPosition::Left, 100)
+        .set_label_area_size(LabelAreaPosition::Bottom, 100)
+        .build_cartesian_2d(max_x..0f32, 0f32..max_y)?;
+
+    chart
+        .configure_mesh()
+        .y_desc("Fraction of Moves")
+        .x_desc(format!(
+            "Time Left on Player Clock ({})",
+            game_reader.clock_unit()
+        ))
+        .axis_desc_style(("sans-serif", 15))
+        .draw()?;
+
+    for ((judgement, rates), color) in Judgement::ERRORS.iter().zip(rates).zip([GREEN, BLUE, RED]) {
+        chart
+            .draw_series(LineSeries::new(
+                rates
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(x, rate)| rate.map(|rate| (x as f32, rate as f32))),
+                color.stroke_width(2),
+            ))?
+            .label(judgement.name())
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 50, y)], color.stroke_width(2))
+            });
     }
     chart.configure_series_labels().draw()?;
     root.present()?;
     Ok(())
 }
+
+// the picked model, generate_plots has already picked the best candidate if none was given
+fn fit_model(
+    options: &PlotOptions,
+    game_ids: &[usize],
+    x_values: &[f64],
+    y_values: &[f64],
+) -> Result<Box<dyn RegressionModel>, Box<dyn std::error::Error>> {
+    pick_model(
+        options.model,
+        game_ids,
+        x_values,
+        y_values,
+        options.folds,
+        options.rank_by,
+    )?
+    .fit(x_values, y_values)
+}
+
+// the regression curve over a time left chart, with the bands shaded around it if asked for
+fn overlay_regression<DB>(
+    chart: &mut ChartContext<'_, DB, Cartesian2d<RangedCoordf32, RangedCoordf32>>,
+    game_reader: &GameReader,
+    options: &PlotOptions,
+    max_x: f32,
+) -> Result<(), Box<dyn Error + 'static>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let (x_values, y_values) = game_reader.xy();
+
+    let model = fit_model(options, &game_reader.moves.game_id, &x_values, &y_values)?;
+
+    if options.bands {
+        let inference = Inference::of(model.as_ref(), &x_values, &y_values, options.confidence)?;
+        let max_y = chart.y_range().end;
+        let level = options.confidence * 100.0;
+        // the wide prediction band first so the confidence band is drawn on top of it
+        for (name, band, opacity) in [
+            ("prediction", Inference::prediction_band as BandFn, 0.15),
+            ("confidence", Inference::confidence_band as BandFn, 0.3),
+        ] {
+            let bounds: Vec<(f32, f32, f32)> = (0..=max_x as usize)
+                .map(|x| (x, band(&inference, model.as_ref(), x as f64)))
+                .filter(|(_, (low, high))| low.is_finite() && high.is_finite())
+                .map(|(x, (low, high))| {
+                    (
+                        x as f32,
+                        (low as f32).clamp(0.0, max_y),
+                        (high as f32).clamp(0.0, max_y),
+                    )
+                })
+                .collect();
+            // along the top and back along the bottom
+            let outline: Vec<(f32, f32)> = bounds
+                .iter()
+                .map(|(x, _, high)| (*x, *high))
+                .chain(bounds.iter().rev().map(|(x, low, _)| (*x, *low)))
+                .collect();
+            let style = GREEN.mix(opacity).filled();
+            chart
+                .draw_series(std::iter::once(Polygon::new(outline, style)))?
+                .label(format!("{}% {} band", level, name))
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 50, y + 5)], style));
+        }
+    }
+
+    chart
+        .draw_series(LineSeries::new(
+            regression_curve(model.as_ref(), max_x),
+            GREEN.stroke_width(2),
+        ))?
+        .label(model.name())
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 50, y)], GREEN.stroke_width(2)));
+    Ok(())
+}
+
+type BandFn = fn(&Inference, &dyn RegressionModel, f64) -> (f64, f64);
+
+// points along a fitted curve, one per second (or percent), leaving out where the model isn't defined
+fn regression_curve(model: &dyn RegressionModel, max_x: f32) -> Vec<(f32, f32)> {
+    (0..=max_x as usize)
+        .map(|x| (x as f32, model.predict(x as f64) as f32))
+        .filter(|(_, y)| y.is_finite())
+        .collect()
+}
+
 fn residuals<T>(
     root: DrawingArea<T, Shift>,
     game_reader: &GameReader,
+    options: &PlotOptions,
     resolution: (u32, u32),
 ) -> Result<(), Box<dyn Error + 'static>>
 where
@@ -149,19 +464,16 @@ where
     <T as DrawingBackend>::ErrorType: 'static,
 {
     // ----- DATA ----- //
-    let (x_values, y_values): (Vec<f64>, Vec<f64>) = game_reader
-        .time_data
-        .iter()
-        .enumerate()
-        .flat_map(|(x, row)| row.iter().map(move |&y| (x as f64, y as f64)))
-        .unzip();
-    let residual_y = generate_residuals(&x_values, &y_values)?;
+    let (x_values, y_values) = game_reader.xy();
+    let residual_y = fit_model(options, &game_reader.moves.game_id, &x_values, &y_values)?
+        .residuals(&x_values, &y_values);
 
     let points = x_values
         .iter()
         .zip(residual_y)
+        .filter(|(_, y)| y.is_finite())
         .map(|(x, y)| Circle::new((*x as f32, y as f32), 2, BLUE.mix(0.05).filled()));
-    let max_x = game_reader.max_allowed_time as f32;
+    let max_x = game_reader.max_clock() as f32;
     let max_y = 100f32;
 
     // ----- chart stuff ----- //
@@ -179,8 +491,11 @@ where
 
     chart
         .configure_mesh()
-        .y_desc("TTM (S)")
-        .x_desc("Time Left on Player Clock (S)")
+        .y_desc(format!("TTM ({})", game_reader.clock_unit()))
+        .x_desc(format!(
+            "Time Left on Player Clock ({})",
+            game_reader.clock_unit()
+        ))
         .axis_desc_style(("sans-serif", 15))
         .draw()?;
     chart.draw_series(points)?;
@@ -193,17 +508,19 @@ where
 fn all_points<T>(
     root: DrawingArea<T, Shift>,
     game_reader: &GameReader,
+    options: &PlotOptions,
     _resolution: (u32, u32),
 ) -> Result<(), Box<dyn Error + 'static>>
 where
     T: IntoDrawingArea,
     <T as DrawingBackend>::ErrorType: 'static,
 {
-    let all_points = game_reader.time_data.iter().enumerate().flat_map(|(i, v)| {
-        v.iter()
-            .map(move |&y| Circle::new((i as f32, y as f32), 2, BLUE.mix(0.01).filled()))
-    });
-    let max_x = game_reader.max_allowed_time as f32;
+    let (x_values, y_values) = game_reader.xy();
+    let all_points = x_values
+        .iter()
+        .zip(&y_values)
+        .map(|(&x, &y)| Circle::new((x as f32, y as f32), 2, BLUE.mix(0.01).filled()));
+    let max_x = game_reader.max_clock() as f32;
     // ----- CHART ----- //
     root.fill(&WHITE)?;
     let mut chart = ChartBuilder::on(&root)
@@ -218,31 +535,17 @@ where
 
     chart
         .configure_mesh()
-        .y_desc("TTM (S)")
-        .x_desc("Time Left on Player Clock (S)")
+        .y_desc(format!("TTM ({})", game_reader.clock_unit()))
+        .x_desc(format!(
+            "Time Left on Player Clock ({})",
+            game_reader.clock_unit()
+        ))
         .axis_desc_style(("sans-serif", 25))
         .draw()?;
 
     chart.draw_series(all_points)?;
-    if game_reader.args.overlay_regression {
-        let (x_values, y_values): (Vec<f64>, Vec<f64>) = game_reader
-            .time_data
-            .iter()
-            .enumerate()
-            .flat_map(|(x, row)| row.iter().map(move |&y| (x as f64, y as f64)))
-            .unzip();
-
-        let r = quadratic_regression(&x_values, &y_values)?;
-
-        chart.draw_series(LineSeries::new(
-            (0..max_x as usize).map(|x| {
-                (
-                    x as f32,
-                    r.0 as f32 * x as f32 * x as f32 + r.1 as f32 * x as f32 + r.2 as f32,
-                )
-            }),
-            GREEN.stroke_width(2),
-        ))?;
+    if options.overlay_regression {
+        overlay_regression(&mut chart, game_reader, options, max_x)?;
     }
     chart.configure_series_labels().draw()?;
     root.present()?;
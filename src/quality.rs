@@ -0,0 +1,179 @@
+// Move quality
+// sorts moves into inaccuracies, mistakes and blunders using the engine evaluations,
+// so we can see how often players go wrong as their clock runs down.
+
+use crate::moves::{Color, MoveRecord};
+use clap::ValueEnum;
+
+/// How bad a move was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    /// Every judgement except Good, mildest first.
+    pub const ERRORS: [Judgement; 3] = [
+        Judgement::Inaccuracy,
+        Judgement::Mistake,
+        Judgement::Blunder,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Judgement::Good => "Good move",
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        }
+    }
+}
+
+/// What a move is judged by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum JudgementMethod {
+    /// The drop in winning chances, like lichess does it
+    #[default]
+    WinChance,
+    /// Centipawns lost (50 for an inaccuracy, 100 for a mistake, 300 for a blunder)
+    Centipawns,
+}
+
+/// Winning chances between -1 and 1 for an evaluation in centipawns, using lichess' curve.
+pub fn win_chance(centipawns: i32) -> f64 {
+    2.0 / (1.0 + (-0.00368208 * centipawns as f64).exp()) - 1.0
+}
+
+/// Judge a move. `None` if it doesn't have evaluations before and after.
+pub fn judge(record: &MoveRecord, method: JudgementMethod) -> Option<Judgement> {
+    let thresholds = match method {
+        JudgementMethod::WinChance => {
+            let before = win_chance(record.eval_before?.centipawns());
+            let after = win_chance(record.eval?.centipawns());
+            let drop = match record.color {
+                Color::White => before - after,
+                Color::Black => after - before,
+            };
+            [drop >= 0.1, drop >= 0.2, drop >= 0.3]
+        }
+        JudgementMethod::Centipawns => {
+            let loss = record.centipawn_loss()?;
+            [loss >= 50, loss >= 100, loss >= 300]
+        }
+    };
+    Some(match thresholds {
+        [_, _, true] => Judgement::Blunder,
+        [_, true, _] => Judgement::Mistake,
+        [true, _, _] => Judgement::Inaccuracy,
+        _ => Judgement::Good,
+    })
+}
+
+/// Fraction of the judged moves in each bucket that were `judgement`,
+/// `None` for buckets without any judged moves.
+pub fn error_rates(judgement_data: &[Vec<Judgement>], judgement: Judgement) -> Vec<Option<f64>> {
+    judgement_data
+        .iter()
+        .map(|moves| {
+            (!moves.is_empty()).then(|| {
+                moves.iter().filter(|j| **j == judgement).count() as f64 / moves.len() as f64
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{error_rates, judge, win_chance, Judgement, JudgementMethod};
+    use crate::annotations::Eval;
+    use crate::moves::{Color, MoveRecord};
+
+    fn record(color: Color, before: i32, after: i32) -> MoveRecord {
+        MoveRecord {
+            color,
+            ply: 10,
+            clock_before: 30_000,
+            clock_after: 25_000,
+            time_spent: 5_000,
+            eval_before: Some(Eval::Centipawns(before)),
+            eval: Some(Eval::Centipawns(after)),
+            ..MoveRecord::example()
+        }
+    }
+
+    #[test]
+    fn win_chances() {
+        assert_eq!(win_chance(0), 0.0);
+        assert!((win_chance(300) - 0.5).abs() < 0.02);
+        assert!((win_chance(-300) + win_chance(300)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn judgements() {
+        let cp = JudgementMethod::Centipawns;
+        assert_eq!(
+            judge(&record(Color::White, 20, 0), cp),
+            Some(Judgement::Good)
+        );
+        assert_eq!(
+            judge(&record(Color::White, 20, -40), cp),
+            Some(Judgement::Inaccuracy)
+        );
+        assert_eq!(
+            judge(&record(Color::White, 20, -100), cp),
+            Some(Judgement::Mistake)
+        );
+        assert_eq!(
+            judge(&record(Color::Black, 20, 400), cp),
+            Some(Judgement::Blunder)
+        );
+        // good for black
+        assert_eq!(
+            judge(&record(Color::Black, 20, -400), cp),
+            Some(Judgement::Good)
+        );
+
+        let wc = JudgementMethod::WinChance;
+        assert_eq!(
+            judge(&record(Color::White, 0, -120), wc),
+            Some(Judgement::Mistake)
+        );
+        assert_eq!(
+            judge(&record(Color::White, 0, -200), wc),
+            Some(Judgement::Blunder)
+        );
+        // the same 200 centipawns barely matter in a won position
+        assert_eq!(
+            judge(&record(Color::White, 900, 700), wc),
+            Some(Judgement::Good)
+        );
+
+        let mut unjudged = record(Color::White, 0, 0);
+        unjudged.eval_before = None;
+        assert_eq!(judge(&unjudged, wc), None);
+    }
+
+    #[test]
+    fn rates() {
+        let data = vec![
+            vec![],
+            vec![
+                Judgement::Good,
+                Judgement::Mistake,
+                Judgement::Blunder,
+                Judgement::Good,
+            ],
+        ];
+        assert_eq!(
+            error_rates(&data, Judgement::Blunder),
+            vec![None, Some(0.25)]
+        );
+        assert_eq!(
+            error_rates(&data, Judgement::Inaccuracy),
+            vec![None, Some(0.0)]
+        );
+    }
+}
@@ -0,0 +1,324 @@
+// Quantile regression
+// time taken is very right-skewed, a handful of long thinks drag the least squares curve up.
+// a quantile curve goes through e.g. the median time taken at each time left instead of the mean,
+// and a few of them (10th, 50th, 90th) show how spread out the times are as the clock runs down.
+
+use crate::analysis::to_precision;
+use crate::regression::{linear_equation, ModelKind, RegressionModel};
+use nalgebra::{DMatrix, DVector, SVD};
+use std::error::Error;
+
+/// A curve through a quantile of y, with the same terms as a least squares model.
+pub struct QuantileModel {
+    /// Between 0 and 1, 0.5 is the median.
+    pub quantile: f64,
+    // the least squares fit, for its terms and the starting point
+    base: Box<dyn RegressionModel>,
+    coefficients: Vec<f64>,
+}
+
+impl QuantileModel {
+    /// Fit the `quantile` curve with the terms of `kind`.
+    ///
+    /// Minimizes the check loss by iteratively reweighted least squares, starting from the least
+    /// squares fit. Curves fitted on `ln(y)` work the same, the quantiles of `ln(y)` are the logs
+    /// of the quantiles of y.
+    pub fn fit(
+        kind: ModelKind,
+        quantile: f64,
+        x_values: &[f64],
+        y_values: &[f64],
+    ) -> Result<QuantileModel, Box<dyn Error>> {
+        if !(quantile > 0.0 && quantile < 1.0) {
+            return Err(format!("Invalid quantile: {}", quantile).into());
+        }
+        let base = kind.fit(x_values, y_values)?;
+        let (rows, y_values) = fitted_points(base.as_ref(), x_values, y_values);
+
+        // residuals closer to 0 than this all get the same (big) weight, otherwise a curve going
+        // right through a point would divide by 0. time taken comes in whole seconds a lot, so
+        // many points sit right on the curve
+        let epsilon =
+            1e-6 * (y_values.iter().map(|y| y.abs()).sum::<f64>() / y_values.len() as f64).max(1.0);
+        let mut coefficients = base.linear_coefficients();
+        let mut loss = check_loss(&rows, &y_values, &coefficients, quantile);
+        for _ in 0..100 {
+            let weights: Vec<f64> = rows
+                .iter()
+                .zip(&y_values)
+                .map(|(terms, y)| {
+                    let residual = y - dot(terms, &coefficients);
+                    let side = if residual > 0.0 {
+                        quantile
+                    } else {
+                        1.0 - quantile
+                    };
+                    side / residual.abs().max(epsilon)
+                })
+                .collect();
+            let next = weighted_least_squares(&rows, &y_values, &weights)?;
+            let next_loss = check_loss(&rows, &y_values, &next, quantile);
+            // the loss should only go down, stop once it doesn't by much
+            let improved = loss - next_loss > 1e-10 * loss.abs();
+            if next_loss < loss {
+                coefficients = next;
+                loss = next_loss;
+            }
+            if !improved {
+                break;
+            }
+        }
+        Ok(QuantileModel {
+            quantile,
+            base,
+            coefficients,
+        })
+    }
+
+    /// Share of the points below the curve, which should come out close to `quantile`.
+    pub fn share_below(&self, x_values: &[f64], y_values: &[f64]) -> f64 {
+        let (below, total) = x_values
+            .iter()
+            .zip(y_values)
+            .map(|(&x, &y)| (y, self.predict(x)))
+            .filter(|(_, predicted)| predicted.is_finite())
+            .fold((0, 0), |(below, total), (y, predicted)| {
+                (below + (y < predicted) as usize, total + 1)
+            });
+        below as f64 / total as f64
+    }
+
+    /// Koenker and Machado's pseudo R^2: how much the curve cuts the check loss compared to
+    /// a flat line at the quantile of y.
+    pub fn pseudo_r_squared(&self, x_values: &[f64], y_values: &[f64]) -> f64 {
+        let (rows, y_values) = fitted_points(self, x_values, y_values);
+        let mut sorted = y_values.clone();
+        sorted.sort_by(f64::total_cmp);
+        let flat = sorted[((sorted.len() as f64 * self.quantile) as usize).min(sorted.len() - 1)];
+        let flat_loss: f64 = y_values
+            .iter()
+            .map(|y| check(y - flat, self.quantile))
+            .sum();
+        1.0 - check_loss(&rows, &y_values, &self.coefficients, self.quantile) / flat_loss
+    }
+}
+
+impl RegressionModel for QuantileModel {
+    fn name(&self) -> String {
+        format!(
+            "{} Percentile {}",
+            ordinal(self.quantile * 100.0),
+            self.base.name()
+        )
+    }
+    fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+    fn predict(&self, x: f64) -> f64 {
+        self.base
+            .unlink(dot(&self.base.terms(x), &self.coefficients))
+    }
+    fn equation(&self) -> String {
+        linear_equation(self, &self.coefficients)
+    }
+    fn terms(&self, x: f64) -> Vec<f64> {
+        self.base.terms(x)
+    }
+    fn term_names(&self) -> Vec<String> {
+        self.base.term_names()
+    }
+    fn link(&self, y: f64) -> f64 {
+        self.base.link(y)
+    }
+    fn unlink(&self, value: f64) -> f64 {
+        self.base.unlink(value)
+    }
+    fn fitted_variable(&self) -> &'static str {
+        self.base.fitted_variable()
+    }
+}
+
+// the terms and transformed y of the points the model can be fitted on
+fn fitted_points(
+    model: &dyn RegressionModel,
+    x_values: &[f64],
+    y_values: &[f64],
+) -> (Vec<Vec<f64>>, Vec<f64>) {
+    x_values
+        .iter()
+        .zip(y_values)
+        .map(|(&x, &y)| (model.terms(x), model.link(y)))
+        .filter(|(terms, y)| y.is_finite() && terms.iter().all(|t| t.is_finite()))
+        .unzip()
+}
+
+/// A percentile written out as 1st, 2nd, 3rd, 11th, 22nd... Fractions like 2.5th just get "th".
+pub fn ordinal(percentile: f64) -> String {
+    // 0.1 * 100 isn't quite 10
+    let percentile = to_precision(percentile, 4);
+    let suffix = match (percentile % 100.0, percentile % 10.0) {
+        (11.0..=13.0, _) => "th",
+        (_, 1.0) => "st",
+        (_, 2.0) => "nd",
+        (_, 3.0) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", percentile, suffix)
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+// the loss quantile regression minimizes: residuals above the curve cost `quantile`,
+// the ones below cost `1 - quantile`
+fn check(residual: f64, quantile: f64) -> f64 {
+    if residual > 0.0 {
+        quantile * residual
+    } else {
+        (quantile - 1.0) * residual
+    }
+}
+
+fn check_loss(rows: &[Vec<f64>], y_values: &[f64], coefficients: &[f64], quantile: f64) -> f64 {
+    rows.iter()
+        .zip(y_values)
+        .map(|(terms, y)| check(y - dot(terms, coefficients), quantile))
+        .sum()
+}
+
+// solve (X^T W X) b = X^T W y. the normal equations are only p x p, so this stays quick with
+// millions of moves, the columns get scaled to length 1 first to keep the powers of x in check
+fn weighted_least_squares(
+    rows: &[Vec<f64>],
+    y_values: &[f64],
+    weights: &[f64],
+) -> Result<Vec<f64>, Box<dyn Error>> {
+    let p = rows.first().map_or(0, |terms| terms.len());
+    if rows.len() < p {
+        return Err(format!(
+            "Not enough points to fit the model ({} points for {} coefficients)",
+            rows.len(),
+            p
+        )
+        .into());
+    }
+    let mut norms = vec![0.0; p];
+    for terms in rows {
+        for (norm, t) in norms.iter_mut().zip(terms) {
+            *norm += t * t;
+        }
+    }
+    let norms: Vec<f64> = norms
+        .into_iter()
+        .map(|n| n.sqrt().max(f64::MIN_POSITIVE))
+        .collect();
+
+    let mut xtwx = DMatrix::<f64>::zeros(p, p);
+    let mut xtwy = DVector::<f64>::zeros(p);
+    for ((terms, y), w) in rows.iter().zip(y_values).zip(weights) {
+        for i in 0..p {
+            let a = terms[i] / norms[i];
+            xtwy[i] += w * a * y;
+            for j in 0..p {
+                xtwx[(i, j)] += w * a * terms[j] / norms[j];
+            }
+        }
+    }
+    let solved = SVD::new(xtwx, true, true).solve(&xtwy, 1e-12)?;
+    Ok(solved
+        .iter()
+        .zip(&norms)
+        .map(|(b, norm)| b / norm)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ordinal, QuantileModel};
+    use crate::analysis::to_precision;
+    use crate::regression::{ModelKind, RegressionModel};
+
+    // 21 points at every x, spread evenly from 0 to 2 times the line 10 + x / 10
+    fn fan() -> (Vec<f64>, Vec<f64>) {
+        let mut x_values = Vec::new();
+        let mut y_values = Vec::new();
+        for x in 0..50 {
+            let x = x as f64 * 4.0;
+            for k in 0..=20 {
+                x_values.push(x);
+                y_values.push((10.0 + x / 10.0) * k as f64 / 10.0);
+            }
+        }
+        (x_values, y_values)
+    }
+
+    #[test]
+    fn quantile_lines() {
+        let (x_values, y_values) = fan();
+        for (quantile, factor) in [(0.1, 0.2), (0.5, 1.0), (0.9, 1.8)] {
+            let model =
+                QuantileModel::fit(ModelKind::Polynomial(1), quantile, &x_values, &y_values)
+                    .unwrap();
+            // the quantile lines fan out from the same spot
+            let coefficients: Vec<f64> = model
+                .coefficients()
+                .iter()
+                .map(|c| to_precision(*c, 2))
+                .collect();
+            assert_eq!(
+                coefficients,
+                vec![to_precision(0.1 * factor, 2), 10.0 * factor]
+            );
+            let below = model.share_below(&x_values, &y_values);
+            assert!((below - quantile).abs() < 0.06, "{} below", below);
+        }
+        let median =
+            QuantileModel::fit(ModelKind::Polynomial(1), 0.5, &x_values, &y_values).unwrap();
+        assert_eq!(median.name(), "50th Percentile Linear Regression");
+        assert_eq!(median.equation(), "0.1x +10");
+        assert!(median.pseudo_r_squared(&x_values, &y_values) > 0.0);
+    }
+
+    #[test]
+    fn ordinals() {
+        let names: Vec<String> = [1.0, 2.0, 3.0, 4.0, 11.0, 12.0, 13.0, 21.0, 22.0, 33.0, 2.5]
+            .into_iter()
+            .map(ordinal)
+            .collect();
+        assert_eq!(
+            names,
+            ["1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "33rd", "2.5th"]
+        );
+        assert_eq!(ordinal(0.1 * 100.0), "10th");
+        assert_eq!(ordinal(0.9 * 100.0), "90th");
+    }
+
+    #[test]
+    fn long_thinks() {
+        // a few huge times pull the mean way up, but barely move the median
+        let x_values: Vec<f64> = (0..200).map(|x| x as f64).collect();
+        let y_values: Vec<f64> = x_values
+            .iter()
+            .map(|x| {
+                if (*x as usize).is_multiple_of(10) {
+                    300.0
+                } else {
+                    5.0 + (*x as usize % 3) as f64
+                }
+            })
+            .collect();
+        let mean = ModelKind::Polynomial(2).fit(&x_values, &y_values).unwrap();
+        let median =
+            QuantileModel::fit(ModelKind::Polynomial(2), 0.5, &x_values, &y_values).unwrap();
+        assert!(mean.predict(100.0) > 30.0);
+        assert!((median.predict(100.0) - 6.0).abs() < 1.0);
+
+        // curves fitted on ln(y) too
+        let power = QuantileModel::fit(ModelKind::Power, 0.5, &x_values, &y_values).unwrap();
+        assert!(power.equation().starts_with("ln(y) = "));
+        assert!((power.predict(100.0) - 6.0).abs() < 1.0);
+
+        assert!(QuantileModel::fit(ModelKind::Polynomial(1), 1.0, &x_values, &y_values).is_err());
+    }
+}
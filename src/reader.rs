@@ -1,53 +1,203 @@
 // Game reader
 // this is essentially the data collection tool
 
-use crate::args::Args;
-use pgn_reader::{Skip, Visitor};
+use crate::annotations::{self, Eval};
+use crate::filter::{BothSides, GameFilter, GameHeaders, PlayerIs, SideFilter, TimeControlIs};
+use crate::moves::{Color, GameResult, MoveContext, MoveRecord, MoveStore, TimeControl};
+use crate::quality::{Judgement, JudgementMethod};
+use clap::ValueEnum;
+use pgn_reader::{SanPlus, Skip, Visitor};
+use shakmaty::{fen::Fen, san::San, CastlingMode, Chess, Position};
+use std::{fmt, sync::Arc};
+
+/// What to do with a game that has a clock we can't read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum BadClocks {
+    /// Throw away the whole game
+    #[default]
+    Skip,
+    /// Keep the game, treating the bad clock as if it wasn't there
+    Flag,
+}
+
+/// Decides which games a GameReader collects moves from.
+#[derive(Clone)]
+pub struct ReaderConfig {
+    /// Stop recording after this many games.
+    pub max_games: Option<usize>,
+    /// Only games accepted by this filter are read.
+    pub filter: Arc<dyn GameFilter>,
+    /// Whose moves are recorded in the games that pass the filter.
+    pub sides: Arc<dyn SideFilter>,
+    pub bad_clocks: BadClocks,
+    /// Players whose moves are tagged with their place in this list (see `MoveRecord::player`),
+    /// whether or not the filter only lets their games through.
+    pub players: PlayerIs,
+}
+
+impl ReaderConfig {
+    /// Read every game with the given time control, no other limits.
+    pub fn new(time_control: TimeControl) -> ReaderConfig {
+        ReaderConfig {
+            max_games: None,
+            filter: Arc::new(TimeControlIs(time_control)),
+            sides: Arc::new(BothSides),
+            bad_clocks: BadClocks::Skip,
+            players: PlayerIs(Vec::new()),
+        }
+    }
+}
+
+impl fmt::Debug for ReaderConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReaderConfig")
+            .field("max_games", &self.max_games)
+            .field("filter", &self.filter.description())
+            .field("bad_clocks", &self.bad_clocks)
+            .field("players", &self.players.0)
+            .finish()
+    }
+}
+
+// how far apart (in milliseconds) %emt and the time taken from %clk can be before we call them inconsistent.
+// both are rounded and lichess adds lag compensation, so they rarely match exactly
+const CLOCK_TOLERANCE: i32 = 1000;
+
 // skipping is hugely important for optimization because it could mean skipping millions of games and saving time
 pub struct GameReader {
     pub games_analyzed: usize,
     pub total_games: usize,
     pub moves_analyzed: usize,
-    pub time_data: Vec<Vec<i32>>,
-    pub args: Args,
-    time_control_offset: i32,
+    /// Games with at least one clock we couldn't read, whether they were skipped or kept.
+    pub bad_clock_games: usize,
+    /// Moves that had both `%clk` and `%emt`, so the two could be compared.
+    pub clock_checks: usize,
+    /// Of those, moves where they disagree by more than a second.
+    pub clock_mismatches: usize,
+    pub moves: MoveStore,
+    pub config: ReaderConfig,
+    /// The largest starting clock of the games read, in seconds.
     pub max_allowed_time: i32,
+    /// Report clock values as a percentage of each game's starting clock instead of seconds.
+    pub normalize: bool,
     is_skipping: bool,
-    prev_times: [i32; 2],
+    // each player's clock after their last move, indexed by Color.
+    // None until we've seen one, or when the last move had no usable clock
+    prev_clocks: [Option<i32>; 2],
+    // whether the move that was just played had a clock comment
+    clock_seen: bool,
+    // whether this game had a clock we couldn't read
+    bad_clock: bool,
+    // engine evaluations before and after the move that was just played
+    eval_before: Option<Eval>,
+    eval: Option<Eval>,
+    // the position before the next move, None once we can't follow the game
+    position: Option<Chess>,
+    // what the move that was just played looked like
+    context: MoveContext,
+    // where this game's moves start in `moves`, so a bad game can be taken back out
+    game_start: usize,
+    headers: GameHeaders,
+    // whether white's and black's moves are recorded in this game
+    keep_sides: [bool; 2],
+    // which of the tracked players white and black are
+    players: [Option<u16>; 2],
+    // who plays ply 1, black when the FEN has black to move
+    first_to_move: Color,
+    // per-game info that gets attached to every move
+    ply: u16,
+    time_control: TimeControl,
+    white_elo: Option<i32>,
+    black_elo: Option<i32>,
+    result: GameResult,
 }
 
 impl GameReader {
-    pub fn new(args: &Args) -> GameReader {
-        // max allowed time will be used to filter garbage data
-        // because there is some, either by my collection methods or in the database
-        // this is a huge optimization of my original implementation
-        // so let me be proud.
-
-        // another note: we're doing a bunch of rather unsafe shit with [] indexing because i will
-        // (or hopefully will) add argument validation to the program :)
-        let times = args.time_control.split('+').collect::<Vec<&str>>();
-        let max_allowed_time = times[0].parse::<i32>().unwrap();
-        let offset = times[1].parse::<i32>().unwrap();
-
-        let max = max_allowed_time + offset;
-        // allows for pretty good optimization huh?
-        // neat trick!
-        let time_map: Vec<Vec<i32>> = vec![Vec::new(); max as usize + 1];
-
+    pub fn new(config: ReaderConfig) -> GameReader {
         GameReader {
             // important stuff
             games_analyzed: 0,
-            time_data: time_map,
-            max_allowed_time: max,
-            args: args.clone(),
-            time_control_offset: offset,
+            moves: MoveStore::new(),
+            // grows with every game we read
+            max_allowed_time: 0,
+            normalize: false,
+            config,
             is_skipping: false,
-            prev_times: [-1, -1],
+            prev_clocks: [None; 2],
+            clock_seen: false,
+            bad_clock: false,
+            eval_before: None,
+            eval: None,
+            position: None,
+            context: MoveContext::default(),
+            game_start: 0,
+            headers: GameHeaders::new(),
+            keep_sides: [true; 2],
+            players: [None; 2],
+            first_to_move: Color::White,
             total_games: 0,
             moves_analyzed: 0,
+            bad_clock_games: 0,
+            clock_checks: 0,
+            clock_mismatches: 0,
+            ply: 0,
+            time_control: TimeControl {
+                base: 0,
+                increment: 0,
+            },
+            white_elo: None,
+            black_elo: None,
+            result: GameResult::Unknown,
         }
     }
 
+    /// The largest possible time left: in seconds, or 100 when normalized.
+    pub fn max_clock(&self) -> i32 {
+        if self.normalize {
+            100
+        } else {
+            self.max_allowed_time
+        }
+    }
+
+    /// Unit of the clock values, for axis labels.
+    pub fn clock_unit(&self) -> &'static str {
+        if self.normalize {
+            "% of starting clock"
+        } else {
+            "S"
+        }
+    }
+
+    /// Time taken for every move, bucketed by the time left on the clock.
+    pub fn time_data(&self) -> Vec<Vec<f64>> {
+        self.moves.time_data(self.max_clock(), self.normalize)
+    }
+
+    /// How good every move with evaluations was, bucketed like `time_data`.
+    pub fn judgement_data(&self, method: JudgementMethod) -> Vec<Vec<Judgement>> {
+        self.moves
+            .judgement_data(self.max_clock(), self.normalize, method)
+    }
+
+    /// Time left and time taken for every move.
+    pub fn xy(&self) -> (Vec<f64>, Vec<f64>) {
+        self.moves.xy(self.normalize)
+    }
+
+    /// Fold the results of another reader (usually one that read a later part of the same file) into this one.
+    pub fn merge(&mut self, other: GameReader) {
+        // game ids in the other reader start from 0, so move them past ours
+        self.moves.append(other.moves, self.total_games);
+        self.games_analyzed += other.games_analyzed;
+        self.total_games += other.total_games;
+        self.moves_analyzed += other.moves_analyzed;
+        self.bad_clock_games += other.bad_clock_games;
+        self.clock_checks += other.clock_checks;
+        self.clock_mismatches += other.clock_mismatches;
+        self.max_allowed_time = self.max_allowed_time.max(other.max_allowed_time);
+    }
+
     fn read_header(
         &mut self,
         key: &[u8],
@@ -59,32 +209,10 @@ impl GameReader {
             return Ok(());
         }
 
+        // hang on to every header, the filter decides what to do with them once we have them all
         let key = std::str::from_utf8(key)?;
-        // skip unnecessary headers
-        if !(key == "TimeControl" || key == "WhiteElo" || key == "BlackElo") {
-            return Ok(());
-        }
-
         let value = std::str::from_utf8(value.0)?;
-        if key == "TimeControl" && value != self.args.time_control {
-            // println!("{}, {}", self.args.time_control, value);
-            self.is_skipping = true;
-            return Ok(());
-        // extra check to see if max/min rating is even specified
-        // if not then we don't even have to waste time doing this
-        } else if (self.args.max_rating.is_some() || self.args.min_rating.is_some())
-            && (key == "WhiteElo" || key == "BlackElo")
-        {
-            // now, NOTE: we ARE assuming that whichever elo comes first is close to the same as the second one.
-            let val = value.parse::<i32>()?;
-            // decide whether or not to skip based on arguments
-            // skip wrong rating
-            if self.args.min_rating.is_some_and(|rating| val < rating)
-                || self.args.max_rating.is_some_and(|rating| val > rating)
-            {
-                self.is_skipping = true;
-            }
-        }
+        self.headers.push(key, value);
         Ok(())
     }
     fn read_comment(
@@ -93,45 +221,212 @@ impl GameReader {
     ) -> Result<(), Box<dyn std::error::Error>> {
         // convert the raw comment into a string slice
         let comment_str = std::str::from_utf8(comment.as_bytes())?;
-        // possible time-saving? not sure if this well ever do anything though.
-        if comment_str.is_empty() {
+        // a bad clock may have told us to drop the rest of the game
+        if self.is_skipping {
             return Ok(());
         }
 
-        // get rid of brackets in comments, clean and split
-        let cleaned = comment_str.replace(&['[', ']'][..], "");
-        let comment_vec: Vec<&str> = cleaned.split(' ').collect();
-
-        // basically if we find %clk, we know the next element is a time, so lets convert that and add it to our vec of times
-
-        for (i, term) in comment_vec.iter().enumerate() {
-            if *term == "%clk" {
-                let remaining_time = convert_time(comment_vec[i + 1])?;
-                //println!("{}", remaining_time);
-                if remaining_time <= self.max_allowed_time {
-                    // initialize first moves
-                    // very important!!
-                    if self.prev_times[1] == -1 {
-                        self.prev_times[1] = remaining_time;
-                    } else if self.prev_times[0] == -1 {
-                        self.prev_times[0] = remaining_time;
-                    } else {
-                        // if we have initialized our first 2 moves, then we can actually start measuring things
-                        let delta_time =
-                            self.prev_times[1] - (remaining_time - self.time_control_offset);
-                        self.time_data[remaining_time as usize].push(delta_time);
-
-                        // update our previous values
-                        self.prev_times[1] = self.prev_times[0];
-                        self.prev_times[0] = remaining_time;
-                        self.moves_analyzed += 1;
-                    }
-                }
+        // comments before the first move mean nothing to us
+        if self.ply == 0 {
+            return Ok(());
+        }
+        let mut clock = None;
+        let mut elapsed = None;
+        for (name, value) in annotations::commands(comment_str) {
+            match name {
+                "clk" => clock = Some(annotations::parse_clock(value)),
+                "emt" => elapsed = Some(annotations::parse_clock(value)),
+                // a bad eval only costs us the eval
+                "eval" => self.eval = annotations::parse_eval(value).ok(),
+                _ => {}
+            }
+        }
+
+        // a second clock for the same move is ignored, but an eval can come in a later comment
+        // than the clock, after the move was recorded
+        if self.clock_seen {
+            if self.moves.len() > self.game_start && self.moves.ply.last() == Some(&self.ply) {
+                *self.moves.eval.last_mut().unwrap() = self.eval;
+            }
+            return Ok(());
+        }
+
+        // %clk wins when we have both, a bad %emt next to it just can't be checked
+        match (clock, elapsed) {
+            (Some(Ok(remaining_time)), elapsed) => {
+                self.read_clock(remaining_time, elapsed.and_then(Result::ok))
             }
+            (Some(Err(_)), _) | (None, Some(Err(_))) => self.bad_clock(),
+            (None, Some(Ok(elapsed))) => self.read_elapsed(elapsed),
+            (None, None) => {}
         }
 
         Ok(())
     }
+
+    /// `remaining_time` is the clock (in milliseconds) of whoever just moved.
+    /// If the move also had an elapsed time, it gets checked against the clocks.
+    fn read_clock(&mut self, remaining_time: i32, elapsed: Option<i32>) {
+        // anything over the starting clock is garbage data
+        // because there is some, either by my collection methods or in the database
+        if remaining_time > self.time_control.starting_clock() * 1000 {
+            return;
+        }
+        self.clock_seen = true;
+
+        // the clock belongs to whoever just moved
+        let color = self.mover();
+        // the first clock of each player only initializes things
+        // very important!!
+        if let Some(clock_before) = self.prev_clocks[color as usize] {
+            let delta_time = clock_before - (remaining_time - self.time_control.increment * 1000);
+            if let Some(elapsed) = elapsed {
+                self.clock_checks += 1;
+                if (elapsed - delta_time).abs() > CLOCK_TOLERANCE {
+                    self.clock_mismatches += 1;
+                }
+            }
+            self.record_move(color, clock_before, remaining_time, delta_time);
+        }
+        self.prev_clocks[color as usize] = Some(remaining_time);
+    }
+
+    /// Rebuild the clock of whoever just moved from the time they spent (`%emt`, in milliseconds),
+    /// for PGNs that don't have `%clk`.
+    fn read_elapsed(&mut self, elapsed: i32) {
+        let color = self.mover();
+        let clock_before = match self.prev_clocks[color as usize] {
+            Some(clock) => clock,
+            // everyone starts with the base time
+            None if self.ply <= 2 => self.time_control.base * 1000,
+            // we lost track of this player's clock, so there's nothing to rebuild from
+            None => return,
+        };
+        // flagged, or the elapsed times don't belong to this time control
+        let Some(remaining_time) = (clock_before - elapsed)
+            .checked_add(self.time_control.increment * 1000)
+            .filter(|&remaining_time| remaining_time >= 0)
+        else {
+            return;
+        };
+        self.clock_seen = true;
+        self.record_move(color, clock_before, remaining_time, elapsed);
+        self.prev_clocks[color as usize] = Some(remaining_time);
+    }
+
+    fn record_move(&mut self, color: Color, clock_before: i32, clock_after: i32, time_spent: i32) {
+        // the clocks still have to be followed for the side we don't keep
+        if !self.keep_sides[color as usize] {
+            return;
+        }
+        self.moves.push(MoveRecord {
+            game_id: self.total_games - 1,
+            color,
+            ply: self.ply,
+            clock_before,
+            clock_after,
+            time_spent,
+            increment: self.time_control.increment,
+            white_elo: self.white_elo,
+            black_elo: self.black_elo,
+            result: self.result,
+            time_control: self.time_control,
+            eval_before: self.eval_before,
+            eval: self.eval,
+            context: self.context,
+            player: self.players[color as usize],
+        });
+        self.moves_analyzed += 1;
+    }
+
+    /// Work out what kind of move `san_plus` is, and play it on the board if we're following the game.
+    fn read_move(&mut self, san_plus: SanPlus) {
+        // the notation tells us most of it, in case we can't replay the game
+        let san = san_plus.san;
+        self.context = MoveContext {
+            capture: matches!(san, San::Normal { capture: true, .. }),
+            check: san_plus.suffix.is_some(),
+            promotion: matches!(
+                san,
+                San::Normal {
+                    promotion: Some(_),
+                    ..
+                }
+            ),
+            castle: matches!(san, San::Castle(_)),
+            legal_moves: None,
+            pieces: None,
+        };
+        let Some(position) = self.position.take() else {
+            return;
+        };
+        let board = position.board();
+        self.context.legal_moves = Some(position.legal_moves().len() as u16);
+        self.context.pieces =
+            Some((board.occupied() ^ board.pawns() ^ board.kings()).count() as u8);
+        // an illegal move means the rest of the game can't be followed
+        let Ok(m) = san.to_move(&position) else {
+            return;
+        };
+        self.context.capture = m.is_capture();
+        self.context.promotion = m.is_promotion();
+        self.context.castle = m.is_castle();
+        if let Ok(position) = position.play(&m) {
+            self.context.check = position.is_check();
+            self.position = Some(position);
+        }
+    }
+
+    /// Whoever played the move that was just read.
+    fn mover(&self) -> Color {
+        // a game from a FEN with black to move is one ply ahead of the usual count
+        Color::of_ply(self.ply + (self.first_to_move == Color::Black) as u16)
+    }
+
+    fn bad_clock(&mut self) {
+        if !self.bad_clock {
+            self.bad_clock = true;
+            self.bad_clock_games += 1;
+        }
+        match self.config.bad_clocks {
+            BadClocks::Skip => {
+                // take back everything this game recorded
+                self.moves_analyzed -= self.moves.len() - self.game_start;
+                self.moves.truncate(self.game_start);
+                self.games_analyzed -= 1;
+                self.is_skipping = true;
+            }
+            // clock_seen stays false, so the next move forgets this player's clock
+            BadClocks::Flag => {}
+        }
+    }
+}
+
+/// The position a game starts from: the `FEN` header if there is one, otherwise the usual one.
+/// `None` for variants, which shakmaty's `Chess` can't play.
+fn start_position(headers: &GameHeaders) -> Option<Chess> {
+    match headers.get("Variant") {
+        None | Some("Standard" | "Chess960" | "From Position") => {}
+        Some(_) => return None,
+    }
+    match headers.get("FEN") {
+        // chess960 castling also understands the standard castling rights
+        Some(fen) => fen
+            .parse::<Fen>()
+            .ok()?
+            .into_position(CastlingMode::Chess960)
+            .ok(),
+        None => Some(Chess::default()),
+    }
+}
+
+/// The side that plays the first move, from the `FEN` header. Read on its own so the clocks
+/// still go to the right player when the position itself can't be set up.
+fn first_to_move(headers: &GameHeaders) -> Color {
+    match headers.get("FEN").and_then(|fen| fen.parse::<Fen>().ok()) {
+        Some(Fen(setup)) if setup.turn == shakmaty::Color::Black => Color::Black,
+        _ => Color::White,
+    }
 }
 
 impl Visitor for GameReader {
@@ -145,10 +440,18 @@ impl Visitor for GameReader {
     fn begin_game(&mut self) {
         // reset variables, IMPORTANT!
         self.is_skipping = false;
-        self.prev_times = [-1; 2];
+        self.prev_clocks = [None; 2];
+        self.clock_seen = false;
+        self.bad_clock = false;
+        self.eval_before = None;
+        self.eval = None;
+        self.position = None;
+        self.game_start = self.moves.len();
+        self.ply = 0;
+        self.headers.clear();
         // decide to skip if we have reached or exceeded the max number of games
         if self
-            .args
+            .config
             .max_games
             .is_some_and(|max_games| self.games_analyzed >= max_games)
         {
@@ -167,12 +470,59 @@ impl Visitor for GameReader {
     // we have the necessary info to determine whether to skip reading the game
     fn end_headers(&mut self) -> Skip {
         if !self.is_skipping {
+            self.is_skipping = !self.config.filter.accept(&self.headers);
+        }
+        if !self.is_skipping {
+            // the clock math needs the time control, so games without one (e.g. correspondence) are no use
+            match self.headers.time_control() {
+                Some(time_control) => self.time_control = time_control,
+                None => self.is_skipping = true,
+            }
+        }
+        if !self.is_skipping {
+            self.keep_sides = [Color::White, Color::Black]
+                .map(|color| self.config.sides.keep(&self.headers, color));
+            // no point reading a game we won't record anything from
+            self.is_skipping = self.keep_sides == [false; 2];
+        }
+        if !self.is_skipping {
+            // keep the metadata we attach to each move
+            self.white_elo = self.headers.white_elo();
+            self.black_elo = self.headers.black_elo();
+            self.result = self
+                .headers
+                .get("Result")
+                .map_or(GameResult::Unknown, GameResult::from_header);
+            self.players = [Color::White, Color::Black].map(|color| {
+                let name = self.headers.player(color)?;
+                self.config.players.position(name).map(|i| i as u16)
+            });
+            self.position = start_position(&self.headers);
+            self.first_to_move = first_to_move(&self.headers);
             self.games_analyzed += 1;
+            self.max_allowed_time = self
+                .max_allowed_time
+                .max(self.time_control.starting_clock());
         }
         self.total_games += 1;
         Skip(self.is_skipping)
     }
 
+    // every move goes through here before its comment, so this is how we know who moved
+    fn san(&mut self, san_plus: SanPlus) {
+        // a move without a clock would make the player's next delta cover two moves, so forget their clock
+        if self.ply > 0 && !self.clock_seen {
+            self.prev_clocks[self.mover() as usize] = None;
+        }
+        self.ply += 1;
+        self.clock_seen = false;
+        // the evaluation after the last move is the one before this move
+        self.eval_before = self.eval.take();
+        if !self.is_skipping {
+            self.read_move(san_plus);
+        }
+    }
+
     // in a game, we want to collect all of the times for each move,
     // so we use this function
     fn comment(&mut self, comment: pgn_reader::RawComment<'_>) {
@@ -182,28 +532,270 @@ impl Visitor for GameReader {
     fn end_game(&mut self) -> Self::Result {}
 }
 
-fn convert_time(time: &str) -> Result<i32, Box<dyn std::error::Error>> {
-    // convert time into a number of seconds
-    // no idea how to fix this error handling :()
-    let mut units = time.split(':');
-    let hours = str::parse::<i32>(units.next().unwrap())?;
-    let minutes = str::parse::<i32>(units.next().unwrap())?;
-    let seconds = str::parse::<i32>(units.next().unwrap())?;
-    let res = hours * 3600 + minutes * 60 + seconds;
-
-    Ok(res)
-}
 #[cfg(test)]
 mod tests {
+    use super::{BadClocks, GameReader, ReaderConfig};
+    use crate::annotations::Eval;
+    use crate::filter::{AcceptAll, PlayerIs, RatingMode, RatingRange};
+    use crate::moves::Color;
+    use pgn_reader::BufferedReader;
+    use std::sync::Arc;
+
     #[test]
-    fn conversion() {
-        use super::convert_time;
-        assert_eq!(convert_time("0:0:0").unwrap(), 0);
-        assert_eq!(convert_time("00:00:00").unwrap(), 0);
-        assert_eq!(convert_time("0:0:1").unwrap(), 1);
-        assert_eq!(convert_time("0:1:01").unwrap(), 61);
-        assert_eq!(convert_time("00:01:01").unwrap(), 61);
-        assert_eq!(convert_time("01:01:01").unwrap(), 3661);
+    fn rated_side_only() {
+        let pgn = "[WhiteElo \"1200\"]\n[BlackElo \"1900\"]\n[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } \
+            2. Nf3 { [%clk 0:00:58] } 2... Nc6 { [%clk 0:00:55] } \
+            3. Bb5 { [%clk 0:00:57] } 3... a6 { [%clk 0:00:50] } 1-0\n\n";
+        let rating = RatingRange {
+            min: Some(1800),
+            max: None,
+            mode: RatingMode::Either,
+        };
+        let mut config = ReaderConfig::new("60+0".parse().unwrap());
+        config.filter = Arc::new(rating);
+        config.sides = Arc::new(rating);
+        let mut gr = GameReader::new(config);
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+
+        // white's moves are skipped, but black's deltas are still right
+        assert_eq!(gr.games_analyzed, 1);
+        assert_eq!(gr.moves.color, vec![Color::Black, Color::Black]);
+        assert_eq!(gr.moves.time_spent, vec![5000, 5000]);
+        assert_eq!(gr.moves_analyzed, 2);
+    }
+
+    #[test]
+    fn bad_clocks() {
+        let pgn = "[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:00:10] } 1... e5 { [%clk 0:00:10] } \
+            2. Nf3 { [%clk 0:00:08.5] } 2... Nc6 { [%clk 0:00:0x] } \
+            3. Bb5 { [%clk 0:00:07.25] } 3... a6 { [%clk 0:00:05] } \
+            4. Ba4 { [%clk 0:00:06] } 4... Nf6 { [%clk 0:00:04] } 1-0\n\n\
+            [TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } \
+            2. Nf3 { [%clk 0:00:58] } 2... Nc6 { [%clk 0:00:55] } 1-0\n\n";
+
+        let mut config = ReaderConfig::new("60+0".parse().unwrap());
+        let mut gr = GameReader::new(config.clone());
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        // only the second game is left
+        assert_eq!(gr.games_analyzed, 1);
+        assert_eq!(gr.bad_clock_games, 1);
+        assert_eq!(gr.moves.game_id, vec![1, 1]);
+        assert_eq!(gr.moves_analyzed, 2);
+
+        config.bad_clocks = BadClocks::Flag;
+        let mut gr = GameReader::new(config);
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        assert_eq!(gr.games_analyzed, 2);
+        assert_eq!(gr.bad_clock_games, 1);
+        // black's move after the bad clock can't be measured
+        assert_eq!(gr.moves.ply, vec![3, 5, 7, 8, 3, 4]);
+        assert_eq!(
+            gr.moves.time_spent,
+            vec![1_500, 1_250, 1_250, 1_000, 2_000, 5_000]
+        );
+    }
+
+    #[test]
+    fn elapsed_times() {
+        // no %clk at all, the clocks come from the time control
+        let pgn = "[TimeControl \"60+2\"]\n\n\
+            1. e4 { [%emt 0:00:01] } 1... e5 { [%emt 0:00:03] } \
+            2. Nf3 { [%emt 0:00:05.5] } 2... Nc6 { [%emt 0:00:02] } 1-0\n\n";
+        let mut gr = GameReader::new(ReaderConfig::new("60+2".parse().unwrap()));
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        assert_eq!(gr.moves.ply, vec![1, 2, 3, 4]);
+        assert_eq!(gr.moves.clock_before, vec![60_000, 60_000, 61_000, 59_000]);
+        assert_eq!(gr.moves.clock_after, vec![61_000, 59_000, 57_500, 59_000]);
+        assert_eq!(gr.moves.time_spent, vec![1_000, 3_000, 5_500, 2_000]);
+        assert_eq!(gr.clock_checks, 0);
+
+        // both, with black's second %emt way off
+        let pgn = "[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] [%emt 0:00:00] } 1... e5 { [%emt 0:00:01] [%clk 0:00:59] } \
+            2. Nf3 { [%clk 0:00:57] [%emt 0:00:03] } 2... Nc6 { [%emt 0:00:09] [%clk 0:00:55] } 1-0\n\n";
+        let mut gr = GameReader::new(ReaderConfig::new("60+0".parse().unwrap()));
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        // %clk is what gets recorded
+        assert_eq!(gr.moves.time_spent, vec![3_000, 4_000]);
+        assert_eq!(gr.clock_checks, 2);
+        assert_eq!(gr.clock_mismatches, 1);
+    }
+
+    #[test]
+    fn evals() {
+        let pgn = "[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%eval 0.3] [%clk 0:01:00] } 1... e5 { [%eval 0.25] [%clk 0:01:00] } \
+            2. Qh5 { [%eval -0.5] [%clk 0:00:58] } 2... Nc6 { [%clk 0:00:55] } \
+            3. Bc4 { [%eval 0.1] [%clk 0:00:57] } 3... Nf6 { [%eval #3] [%clk 0:00:50] } 1-0\n\n";
+        let mut gr = GameReader::new(ReaderConfig::new("60+0".parse().unwrap()));
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        assert_eq!(
+            gr.moves.eval,
+            vec![
+                Some(Eval::Centipawns(-50)),
+                None,
+                Some(Eval::Centipawns(10)),
+                Some(Eval::Mate(3))
+            ]
+        );
+        assert_eq!(
+            gr.moves.eval_before,
+            vec![
+                Some(Eval::Centipawns(25)),
+                Some(Eval::Centipawns(-50)),
+                None,
+                Some(Eval::Centipawns(10))
+            ]
+        );
+        let losses: Vec<_> = gr.moves.iter().map(|m| m.centipawn_loss()).collect();
+        assert_eq!(losses, vec![Some(75), None, None, Some(990)]);
+    }
+
+    #[test]
+    fn eval_after_clock() {
+        // the eval comes in its own comment after the one with the clock
+        let pgn = "[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] } { [%eval 0.3] } 1... e5 { [%clk 0:01:00] } { [%eval 0.25] } \
+            2. Qh5 { [%clk 0:00:58] } { [%eval -0.5] } { [%clk 0:00:10] } \
+            2... Nc6 { [%clk 0:00:55] } { [%eval 0.2] } 1-0\n\n";
+        let mut gr = GameReader::new(ReaderConfig::new("60+0".parse().unwrap()));
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        assert_eq!(
+            gr.moves.eval,
+            vec![Some(Eval::Centipawns(-50)), Some(Eval::Centipawns(20))]
+        );
+        assert_eq!(
+            gr.moves.eval_before,
+            vec![Some(Eval::Centipawns(25)), Some(Eval::Centipawns(-50))]
+        );
+        // the second clock is still ignored
+        assert_eq!(gr.moves.clock_after, vec![58_000, 55_000]);
+    }
+
+    #[test]
+    fn move_context() {
+        let pgn = "[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] } 1... f5 { [%clk 0:01:00] } \
+            2. exf5 { [%clk 0:00:59] } 2... g5 { [%clk 0:00:58] } \
+            3. Qh5# { [%clk 0:00:57] } 1-0\n\n\
+            [TimeControl \"60+0\"]\n[FEN \"4k3/1P6/8/8/8/8/8/4K2R w K - 0 1\"]\n\n\
+            1. b8=Q+ { [%clk 0:01:00] } 1... Kd7 { [%clk 0:01:00] } \
+            2. O-O { [%clk 0:00:59] } 2... Ke6 { [%clk 0:00:58] } 1-0\n\n";
+        let mut gr = GameReader::new(ReaderConfig::new("60+0".parse().unwrap()));
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        let context = |i: usize| gr.moves.context[i];
+        assert!(context(0).capture && !context(0).check);
+        assert!(context(2).check && !context(2).capture);
+        assert_eq!(
+            gr.moves
+                .context
+                .iter()
+                .map(|c| c.legal_moves)
+                .collect::<Vec<_>>(),
+            vec![Some(31), Some(19), Some(31), Some(36), Some(3)]
+        );
+        // the first clocks only initialize, so the promotion itself isn't recorded
+        assert!(context(3).castle);
+        assert_eq!(gr.moves.ply, vec![3, 4, 5, 3, 4]);
+    }
+
+    #[test]
+    fn black_to_move() {
+        // black plays first, so every clock belongs to the other player than the ply count says
+        let pgn = "[White \"Alice\"]\n[Black \"Bob\"]\n[TimeControl \"60+0\"]\n\
+            [FEN \"4k3/8/8/8/8/8/8/4K2R b K - 0 1\"]\n\n\
+            1... Kd7 { [%clk 0:01:00] } 2. Kd2 { [%clk 0:00:58] } \
+            2... Ke6 { [%clk 0:00:55] } 3. Ke3 { [%clk 0:00:57] } \
+            3... Kd7 { [%clk 0:00:54] } 1/2-1/2\n\n";
+        let mut config = ReaderConfig::new("60+0".parse().unwrap());
+        config.players = PlayerIs(vec!["Bob".to_string()]);
+        let mut gr = GameReader::new(config);
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        assert_eq!(
+            gr.moves.color,
+            vec![Color::Black, Color::White, Color::Black]
+        );
+        assert_eq!(gr.moves.time_spent, vec![5000, 1000, 1000]);
+        assert_eq!(gr.moves.player, vec![Some(0), None, Some(0)]);
+    }
+
+    #[test]
+    fn no_clock() {
+        // a 0+0 game has nothing to normalize by, so it's skipped instead of turning into NaN
+        let pgn = "[TimeControl \"0+0\"]\n\n\
+            1. e4 { [%clk 0:00:00] } 1... e5 { [%clk 0:00:00] } \
+            2. Nf3 { [%clk 0:00:00] } 2... Nc6 { [%clk 0:00:00] } 1-0\n\n\
+            [TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } \
+            2. Nf3 { [%clk 0:00:58] } 2... Nc6 { [%clk 0:00:55] } 1-0\n\n";
+        let mut config = ReaderConfig::new("60+0".parse().unwrap());
+        config.filter = Arc::new(AcceptAll);
+        let mut gr = GameReader::new(config);
+        gr.normalize = true;
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        assert_eq!(gr.games_analyzed, 1);
+        let (x_values, y_values) = gr.xy();
+        assert_eq!(x_values.len(), 2);
+        assert!(x_values.iter().chain(&y_values).all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn tracked_players() {
+        let pgn = "[White \"Alice\"]\n[Black \"bob\"]\n[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } \
+            2. Nf3 { [%clk 0:00:58] } 2... Nc6 { [%clk 0:00:55] } 1-0\n\n";
+        let mut config = ReaderConfig::new("60+0".parse().unwrap());
+        config.players = PlayerIs(vec!["Bob".to_string(), "carol".to_string()]);
+        let mut gr = GameReader::new(config);
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+        assert_eq!(gr.moves.player, vec![None, Some(0)]);
+    }
+
+    #[test]
+    fn missing_clock() {
+        // black's second move has no clock, so black's next move can't be measured,
+        // but white's moves stay on white's timeline
+        let pgn = "[TimeControl \"60+0\"]\n\n\
+            1. e4 { [%clk 0:01:00] } 1... e5 { [%clk 0:01:00] } \
+            2. Nf3 { [%clk 0:00:58] } 2... Nc6 \
+            3. Bb5 { [%clk 0:00:57] } 3... a6 { [%clk 0:00:50] } \
+            4. Ba4 { [%clk 0:00:55] } 4... Nf6 { [%clk 0:00:49] } 1-0\n\n";
+        let mut gr = GameReader::new(ReaderConfig::new("60+0".parse().unwrap()));
+        BufferedReader::new(pgn.as_bytes())
+            .read_all(&mut gr)
+            .unwrap();
+
+        assert_eq!(
+            gr.moves.color,
+            vec![Color::White, Color::White, Color::White, Color::Black]
+        );
+        assert_eq!(gr.moves.ply, vec![3, 5, 7, 8]);
+        assert_eq!(gr.moves.time_spent, vec![2000, 1000, 2000, 1000]);
     }
 }
 
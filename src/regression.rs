@@ -0,0 +1,763 @@
+// Regression models
+// every curve we fit to the time data is a RegressionModel, so the analysis and the plots can
+// work with whichever one was picked without caring how it was fitted.
+
+use crate::analysis::to_precision;
+use crate::bootstrap::clusters;
+use clap::ValueEnum;
+use nalgebra::{DMatrix, DVector, SVD};
+use std::{error::Error, fmt, str::FromStr};
+
+/// A curve fitted to `(x, y)` points.
+pub trait RegressionModel {
+    /// Name of the kind of model, e.g. "Quadratic Regression".
+    fn name(&self) -> String;
+
+    /// The fitted coefficients, in the order they appear in `equation`.
+    fn coefficients(&self) -> &[f64];
+
+    fn predict(&self, x: f64) -> f64;
+
+    /// The fitted curve written out, e.g. `0.25x^2 +0.28x +9.89`.
+    fn equation(&self) -> String;
+
+    /// The terms of the least squares problem the model was fitted as, at `x`:
+    /// `link(y) = linear_coefficients · terms(x)`.
+    fn terms(&self, x: f64) -> Vec<f64>;
+
+    /// Names of the terms, e.g. `x^2`.
+    fn term_names(&self) -> Vec<String>;
+
+    /// The coefficients of the terms. The same as `coefficients` unless the fit was on `ln(y)`.
+    fn linear_coefficients(&self) -> Vec<f64> {
+        self.coefficients().to_vec()
+    }
+
+    /// What y was transformed with before fitting.
+    fn link(&self, y: f64) -> f64 {
+        y
+    }
+
+    /// Undo `link`.
+    fn unlink(&self, value: f64) -> f64 {
+        value
+    }
+
+    /// What the terms add up to, `ln(y)` for the curves fitted on it.
+    fn fitted_variable(&self) -> &'static str {
+        "y"
+    }
+
+    /// Actual minus predicted for every point.
+    /// Points the model isn't defined at (like `ln(0)`) have a non-finite residual.
+    fn residuals(&self, x_values: &[f64], y_values: &[f64]) -> Vec<f64> {
+        x_values
+            .iter()
+            .zip(y_values)
+            .map(|(&x, &y)| y - self.predict(x))
+            .collect()
+    }
+
+    /// Coefficient of determination, over the points the model is defined at.
+    fn r_squared(&self, x_values: &[f64], y_values: &[f64]) -> f64 {
+        let (residuals, y_values): (Vec<f64>, Vec<f64>) = self
+            .residuals(x_values, y_values)
+            .into_iter()
+            .zip(y_values)
+            .filter(|(r, _)| r.is_finite())
+            .unzip();
+        let sse: f64 = residuals.iter().map(|r| r.powi(2)).sum();
+        let mean = y_values.iter().sum::<f64>() / y_values.len() as f64;
+        let sst: f64 = y_values.iter().map(|y| (y - mean).powi(2)).sum();
+        1.0 - sse / sst
+    }
+}
+
+/// Which model to fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelKind {
+    /// Polynomial of this degree (1 is a straight line)
+    Polynomial(usize),
+    /// `a + b ln(x)`
+    Logarithmic,
+    /// `a e^(bx)`
+    Exponential,
+    /// `a x^b`
+    Power,
+}
+
+impl Default for ModelKind {
+    fn default() -> Self {
+        ModelKind::Polynomial(2)
+    }
+}
+
+impl ModelKind {
+    /// Fit this kind of model to the points.
+    pub fn fit(
+        &self,
+        x_values: &[f64],
+        y_values: &[f64],
+    ) -> Result<Box<dyn RegressionModel>, Box<dyn Error>> {
+        Ok(match *self {
+            ModelKind::Polynomial(degree) => Box::new(Polynomial::fit(x_values, y_values, degree)?),
+            ModelKind::Logarithmic => Box::new(Logarithmic::fit(x_values, y_values)?),
+            ModelKind::Exponential => Box::new(Exponential::fit(x_values, y_values)?),
+            ModelKind::Power => Box::new(Power::fit(x_values, y_values)?),
+        })
+    }
+}
+
+impl FromStr for ModelKind {
+    type Err = String;
+
+    /// `linear`, `quadratic`, `cubic`, `poly<degree>` (e.g. `poly4`), `log`, `exp` or `power`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "linear" => Ok(ModelKind::Polynomial(1)),
+            "quadratic" => Ok(ModelKind::Polynomial(2)),
+            "cubic" => Ok(ModelKind::Polynomial(3)),
+            "log" | "logarithmic" => Ok(ModelKind::Logarithmic),
+            "exp" | "exponential" => Ok(ModelKind::Exponential),
+            "power" => Ok(ModelKind::Power),
+            _ => s
+                .strip_prefix("poly")
+                .and_then(|degree| degree.parse().ok())
+                .filter(|degree| *degree >= 1)
+                .map(ModelKind::Polynomial)
+                .ok_or_else(|| format!("Invalid model: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ModelKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelKind::Polynomial(1) => write!(f, "linear"),
+            ModelKind::Polynomial(2) => write!(f, "quadratic"),
+            ModelKind::Polynomial(3) => write!(f, "cubic"),
+            ModelKind::Polynomial(degree) => write!(f, "poly{}", degree),
+            ModelKind::Logarithmic => write!(f, "log"),
+            ModelKind::Exponential => write!(f, "exp"),
+            ModelKind::Power => write!(f, "power"),
+        }
+    }
+}
+
+/// The models tried when no model is picked: polynomials up to degree 5, log and power curves.
+pub const CANDIDATES: [ModelKind; 7] = [
+    ModelKind::Polynomial(1),
+    ModelKind::Polynomial(2),
+    ModelKind::Polynomial(3),
+    ModelKind::Polynomial(4),
+    ModelKind::Polynomial(5),
+    ModelKind::Logarithmic,
+    ModelKind::Power,
+];
+
+/// What candidate models are ranked by. Lower is better for all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Criterion {
+    /// Root mean squared error on the held out points of k-fold cross-validation
+    #[default]
+    Cv,
+    /// Akaike information criterion
+    Aic,
+    /// Bayesian information criterion, punishes extra coefficients harder than AIC
+    Bic,
+}
+
+/// How well one kind of model fits the data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelScore {
+    pub kind: ModelKind,
+    pub r_squared: f64,
+    pub aic: f64,
+    pub bic: f64,
+    /// Cross-validated RMSE, infinite if the model couldn't be fitted in some fold.
+    pub cv_error: f64,
+}
+
+impl ModelScore {
+    pub fn get(&self, criterion: Criterion) -> f64 {
+        match criterion {
+            Criterion::Cv => self.cv_error,
+            Criterion::Aic => self.aic,
+            Criterion::Bic => self.bic,
+        }
+    }
+}
+
+/// Fit every candidate and rank them, best first. Candidates that can't be fitted are left out.
+///
+/// All of them are scored on the same points (the ones every fitted candidate is defined at),
+/// otherwise log and power curves would get to skip the moves at 0 seconds left.
+/// The games (`game_ids` of the points) are split into `folds` folds for the cross-validation,
+/// so moves of the same game never end up on both sides.
+pub fn rank_models(
+    game_ids: &[usize],
+    x_values: &[f64],
+    y_values: &[f64],
+    candidates: &[ModelKind],
+    folds: usize,
+    criterion: Criterion,
+) -> Vec<ModelScore> {
+    let fitted: Vec<(ModelKind, Box<dyn RegressionModel>)> = candidates
+        .iter()
+        .filter_map(|kind| Some((*kind, kind.fit(x_values, y_values).ok()?)))
+        .collect();
+    let residuals: Vec<Vec<f64>> = fitted
+        .iter()
+        .map(|(_, model)| model.residuals(x_values, y_values))
+        .collect();
+    let kept: Vec<usize> = (0..x_values.len())
+        .filter(|&i| residuals.iter().all(|r| r[i].is_finite()))
+        .collect();
+    let pick = |values: &[f64]| -> Vec<f64> { kept.iter().map(|&i| values[i]).collect() };
+    let (x_values, y_values) = (pick(x_values), pick(y_values));
+
+    let game_ids: Vec<usize> = kept.iter().map(|&i| game_ids[i]).collect();
+    let (fold_of, folds) = game_folds(&game_ids, folds);
+
+    let n = x_values.len() as f64;
+    let mut scores: Vec<ModelScore> = fitted
+        .iter()
+        .map(|(kind, model)| {
+            let sse: f64 = model
+                .residuals(&x_values, &y_values)
+                .iter()
+                .map(|r| r.powi(2))
+                .sum();
+            let k = model.coefficients().len() as f64;
+            // the gaussian likelihood with the constant terms dropped, they're the same for every model
+            let fit = n * (sse / n).ln();
+            ModelScore {
+                kind: *kind,
+                r_squared: model.r_squared(&x_values, &y_values),
+                aic: fit + 2.0 * k,
+                bic: fit + k * n.ln(),
+                cv_error: cross_validate(*kind, &x_values, &y_values, &fold_of, folds),
+            }
+        })
+        .collect();
+    scores.sort_by(|a, b| a.get(criterion).total_cmp(&b.get(criterion)));
+    scores
+}
+
+// root mean squared error of predicting each fold from a model fitted to the others
+fn cross_validate(
+    kind: ModelKind,
+    x_values: &[f64],
+    y_values: &[f64],
+    fold_of: &[usize],
+    folds: usize,
+) -> f64 {
+    let mut sse = 0.0;
+    let mut count = 0;
+    for fold in 0..folds {
+        let (train, test): (Vec<usize>, Vec<usize>) =
+            (0..x_values.len()).partition(|&i| fold_of[i] != fold);
+        let pick = |indices: &[usize], values: &[f64]| -> Vec<f64> {
+            indices.iter().map(|&i| values[i]).collect()
+        };
+        let Ok(model) = kind.fit(&pick(&train, x_values), &pick(&train, y_values)) else {
+            return f64::INFINITY;
+        };
+        for r in model.residuals(&pick(&test, x_values), &pick(&test, y_values)) {
+            if !r.is_finite() {
+                // a held out point the model can't predict, like x = 0 for a log curve fitted without it
+                return f64::INFINITY;
+            }
+            sse += r.powi(2);
+            count += 1;
+        }
+    }
+    (sse / count as f64).sqrt()
+}
+
+// the fold of every point, and how many folds there are once there's at least a game per fold.
+// each game goes in one fold, taking turns in the order the games show up
+fn game_folds(game_ids: &[usize], folds: usize) -> (Vec<usize>, usize) {
+    let games = clusters(game_ids);
+    let folds = folds.clamp(2, games.len().max(2));
+    let mut fold_of = vec![0; game_ids.len()];
+    for (game, moves) in games.iter().enumerate() {
+        for &i in moves {
+            fold_of[i] = game % folds;
+        }
+    }
+    (fold_of, folds)
+}
+
+/// The model to use: `model` if one was picked, otherwise the best candidate by `criterion`.
+pub fn pick_model(
+    model: Option<ModelKind>,
+    game_ids: &[usize],
+    x_values: &[f64],
+    y_values: &[f64],
+    folds: usize,
+    criterion: Criterion,
+) -> Result<ModelKind, Box<dyn Error>> {
+    match model {
+        Some(model) => Ok(model),
+        None => rank_models(game_ids, x_values, y_values, &CANDIDATES, folds, criterion)
+            .first()
+            .map(|score| score.kind)
+            .ok_or_else(|| "None of the candidate models could be fitted".into()),
+    }
+}
+
+/// `coefficients` of the model's terms written out, e.g. `0.25x^2 +0.28x +9.89`,
+/// with `ln(y) =` in front for the models fitted on `ln(y)`.
+pub fn linear_equation(model: &dyn RegressionModel, coefficients: &[f64]) -> String {
+    let sum = coefficients
+        .iter()
+        .zip(model.term_names())
+        .enumerate()
+        .map(|(j, (c, term))| {
+            let sign = if j > 0 && c.is_sign_positive() {
+                "+"
+            } else {
+                ""
+            };
+            // the higher powers of seconds get tiny coefficients that would round off to 0
+            let c = if c.abs() < 0.0001 && *c != 0.0 {
+                format!("{:.4e}", c)
+            } else {
+                to_precision(*c, 4).to_string()
+            };
+            if term == "1" {
+                format!("{}{}", sign, c)
+            } else {
+                format!("{}{}{}", sign, c, term)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    match model.fitted_variable() {
+        "y" => sum,
+        variable => format!("{} = {}", variable, sum),
+    }
+}
+
+// solve the least squares problem for a design matrix with one row per point
+fn least_squares(rows: DMatrix<f64>, y_values: &[f64]) -> Result<Vec<f64>, Box<dyn Error>> {
+    if rows.nrows() < rows.ncols() {
+        return Err(format!(
+            "Not enough points to fit the model ({} points for {} coefficients)",
+            rows.nrows(),
+            rows.ncols()
+        )
+        .into());
+    }
+    let y_vector = DVector::from_vec(y_values.to_vec());
+    let svd = SVD::new(rows, true, true);
+    let result = svd.solve(&y_vector, nalgebra::convert(1.0e-10))?;
+    Ok(result.iter().copied().collect())
+}
+
+// the points where both transformed values are usable, transformed
+fn transformed(
+    x_values: &[f64],
+    y_values: &[f64],
+    fx: impl Fn(f64) -> f64,
+    fy: impl Fn(f64) -> f64,
+) -> (Vec<f64>, Vec<f64>) {
+    x_values
+        .iter()
+        .zip(y_values)
+        .map(|(&x, &y)| (fx(x), fy(y)))
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .unzip()
+}
+
+// `a + b x` through the points, as [a, b]
+fn line(x_values: &[f64], y_values: &[f64]) -> Result<Vec<f64>, Box<dyn Error>> {
+    let rows = DMatrix::from_fn(x_values.len(), 2, |i, j| x_values[i].powi(j as i32));
+    least_squares(rows, y_values)
+}
+
+/// `c0 x^n + c1 x^(n-1) + ... + cn`, highest power first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial {
+    pub coefficients: Vec<f64>,
+}
+
+impl Polynomial {
+    pub fn fit(
+        x_values: &[f64],
+        y_values: &[f64],
+        degree: usize,
+    ) -> Result<Polynomial, Box<dyn Error>> {
+        // the powers of a few hundred seconds get huge, so fit on x scaled to about 1 and scale back
+        let scale = x_values.iter().fold(0f64, |a, x| a.max(x.abs())).max(1.0);
+        let rows = DMatrix::from_fn(x_values.len(), degree + 1, |i, j| {
+            (x_values[i] / scale).powi((degree - j) as i32)
+        });
+        let coefficients = least_squares(rows, y_values)?
+            .into_iter()
+            .enumerate()
+            .map(|(j, c)| c / scale.powi((degree - j) as i32))
+            .collect();
+        Ok(Polynomial { coefficients })
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+}
+
+impl RegressionModel for Polynomial {
+    fn name(&self) -> String {
+        match self.degree() {
+            0 => "Constant".to_string(),
+            1 => "Linear Regression".to_string(),
+            2 => "Quadratic Regression".to_string(),
+            3 => "Cubic Regression".to_string(),
+            degree => format!("Degree {} Polynomial Regression", degree),
+        }
+    }
+    fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+    fn predict(&self, x: f64) -> f64 {
+        // horner's method
+        self.coefficients.iter().fold(0.0, |acc, c| acc * x + c)
+    }
+    fn equation(&self) -> String {
+        linear_equation(self, &self.coefficients)
+    }
+    fn terms(&self, x: f64) -> Vec<f64> {
+        (0..=self.degree())
+            .rev()
+            .map(|power| x.powi(power as i32))
+            .collect()
+    }
+    fn term_names(&self) -> Vec<String> {
+        (0..=self.degree())
+            .rev()
+            .map(|power| match power {
+                0 => "1".to_string(),
+                1 => "x".to_string(),
+                power => format!("x^{}", power),
+            })
+            .collect()
+    }
+}
+
+/// `a + b ln(x)`. Only points with a positive x can be used to fit it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Logarithmic {
+    /// `[a, b]`
+    pub coefficients: Vec<f64>,
+}
+
+impl Logarithmic {
+    pub fn fit(x_values: &[f64], y_values: &[f64]) -> Result<Logarithmic, Box<dyn Error>> {
+        let (x, y) = transformed(x_values, y_values, f64::ln, |y| y);
+        Ok(Logarithmic {
+            coefficients: line(&x, &y)?,
+        })
+    }
+}
+
+impl RegressionModel for Logarithmic {
+    fn name(&self) -> String {
+        "Logarithmic Regression".to_string()
+    }
+    fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+    fn predict(&self, x: f64) -> f64 {
+        self.coefficients[0] + self.coefficients[1] * x.ln()
+    }
+    fn equation(&self) -> String {
+        format!(
+            "{} {:+} ln(x)",
+            to_precision(self.coefficients[0], 4),
+            to_precision(self.coefficients[1], 4)
+        )
+    }
+    fn terms(&self, x: f64) -> Vec<f64> {
+        vec![1.0, x.ln()]
+    }
+    fn term_names(&self) -> Vec<String> {
+        vec!["1".to_string(), "ln(x)".to_string()]
+    }
+}
+
+/// `a e^(bx)`, fitted as a line through `ln(y)`, so only points with a positive y can be used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exponential {
+    /// `[a, b]`
+    pub coefficients: Vec<f64>,
+}
+
+impl Exponential {
+    pub fn fit(x_values: &[f64], y_values: &[f64]) -> Result<Exponential, Box<dyn Error>> {
+        let (x, y) = transformed(x_values, y_values, |x| x, f64::ln);
+        let line = line(&x, &y)?;
+        Ok(Exponential {
+            coefficients: vec![line[0].exp(), line[1]],
+        })
+    }
+}
+
+impl RegressionModel for Exponential {
+    fn name(&self) -> String {
+        "Exponential Regression".to_string()
+    }
+    fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+    fn predict(&self, x: f64) -> f64 {
+        self.coefficients[0] * (self.coefficients[1] * x).exp()
+    }
+    fn equation(&self) -> String {
+        format!(
+            "{} e^({}x)",
+            to_precision(self.coefficients[0], 4),
+            to_precision(self.coefficients[1], 6)
+        )
+    }
+    fn terms(&self, x: f64) -> Vec<f64> {
+        vec![1.0, x]
+    }
+    fn term_names(&self) -> Vec<String> {
+        vec!["1".to_string(), "x".to_string()]
+    }
+    fn linear_coefficients(&self) -> Vec<f64> {
+        vec![self.coefficients[0].ln(), self.coefficients[1]]
+    }
+    fn link(&self, y: f64) -> f64 {
+        y.ln()
+    }
+    fn unlink(&self, value: f64) -> f64 {
+        value.exp()
+    }
+    fn fitted_variable(&self) -> &'static str {
+        "ln(y)"
+    }
+}
+
+/// `a x^b`, fitted as a line through `ln(x)` and `ln(y)`, so only points where both are positive can be used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Power {
+    /// `[a, b]`
+    pub coefficients: Vec<f64>,
+}
+
+impl Power {
+    pub fn fit(x_values: &[f64], y_values: &[f64]) -> Result<Power, Box<dyn Error>> {
+        let (x, y) = transformed(x_values, y_values, f64::ln, f64::ln);
+        let line = line(&x, &y)?;
+        Ok(Power {
+            coefficients: vec![line[0].exp(), line[1]],
+        })
+    }
+}
+
+impl RegressionModel for Power {
+    fn name(&self) -> String {
+        "Power Regression".to_string()
+    }
+    fn coefficients(&self) -> &[f64] {
+        &self.coefficients
+    }
+    fn predict(&self, x: f64) -> f64 {
+        self.coefficients[0] * x.powf(self.coefficients[1])
+    }
+    fn equation(&self) -> String {
+        format!(
+            "{} x^{}",
+            to_precision(self.coefficients[0], 4),
+            to_precision(self.coefficients[1], 4)
+        )
+    }
+    fn terms(&self, x: f64) -> Vec<f64> {
+        vec![1.0, x.ln()]
+    }
+    fn term_names(&self) -> Vec<String> {
+        vec!["1".to_string(), "ln(x)".to_string()]
+    }
+    fn linear_coefficients(&self) -> Vec<f64> {
+        vec![self.coefficients[0].ln(), self.coefficients[1]]
+    }
+    fn link(&self, y: f64) -> f64 {
+        y.ln()
+    }
+    fn unlink(&self, value: f64) -> f64 {
+        value.exp()
+    }
+    fn fitted_variable(&self) -> &'static str {
+        "ln(y)"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        game_folds, pick_model, rank_models, to_precision, Criterion, ModelKind, Polynomial,
+        RegressionModel, CANDIDATES,
+    };
+
+    fn rounded(coefficients: &[f64]) -> Vec<f64> {
+        coefficients.iter().map(|c| to_precision(*c, 4)).collect()
+    }
+
+    #[test]
+    fn polynomials() {
+        // same data as the quadratic regression test in analysis
+        let x_values = [-5.0, -4.0, -3.0, -2.0, -1.0, 0.0, 1.0, 2.0, 3.0, 4.0];
+        let y_values = [
+            12.55, 15.61, 10.20, 11.77, 10.24, 9.84, 8.07, 11.63, 12.82, 15.85,
+        ];
+        let model = Polynomial::fit(&x_values, &y_values, 2).unwrap();
+        assert_eq!(rounded(model.coefficients()), vec![0.2484, 0.2837, 9.8881]);
+        assert_eq!(model.equation(), "0.2484x^2 +0.2837x +9.8881");
+
+        // an exact cubic over clock-sized x values
+        let x_values: Vec<f64> = (0..600).step_by(20).map(f64::from).collect();
+        let y_values: Vec<f64> = x_values
+            .iter()
+            .map(|x| 0.000002 * x.powi(3) - 0.001 * x * x + 0.5 * x - 3.0)
+            .collect();
+        let model = ModelKind::Polynomial(3).fit(&x_values, &y_values).unwrap();
+        assert_eq!(model.name(), "Cubic Regression");
+        assert!(
+            (model.predict(250.0) - (0.000002 * 250f64.powi(3) - 62.5 + 125.0 - 3.0)).abs() < 1e-6
+        );
+        assert!((model.r_squared(&x_values, &y_values) - 1.0).abs() < 1e-9);
+        assert_eq!(model.equation(), "2.0000e-6x^3 -0.001x^2 +0.5x -3");
+
+        let line = ModelKind::Polynomial(1)
+            .fit(&[0.0, 1.0, 2.0], &[1.0, 3.0, 5.0])
+            .unwrap();
+        assert_eq!(rounded(line.coefficients()), vec![2.0, 1.0]);
+        assert!(ModelKind::Polynomial(3)
+            .fit(&[0.0, 1.0], &[1.0, 3.0])
+            .is_err());
+    }
+
+    #[test]
+    fn other_models() {
+        let x_values: Vec<f64> = (1..=20).map(f64::from).collect();
+        let fit = |kind: ModelKind, f: &dyn Fn(f64) -> f64| {
+            let y_values: Vec<f64> = x_values.iter().map(|&x| f(x)).collect();
+            let model = kind.fit(&x_values, &y_values).unwrap();
+            assert!((model.r_squared(&x_values, &y_values) - 1.0).abs() < 1e-9);
+            rounded(model.coefficients())
+        };
+        assert_eq!(
+            fit(ModelKind::Logarithmic, &|x| 2.0 + 3.0 * x.ln()),
+            vec![2.0, 3.0]
+        );
+        assert_eq!(
+            fit(ModelKind::Exponential, &|x| 5.0 * (-0.1 * x).exp()),
+            vec![5.0, -0.1]
+        );
+        assert_eq!(
+            fit(ModelKind::Power, &|x| 1.5 * x.powf(0.5)),
+            vec![1.5, 0.5]
+        );
+
+        // points the model can't use are left out of the fit
+        let model = ModelKind::Power
+            .fit(&[0.0, 1.0, 4.0, 9.0], &[7.0, 1.0, 2.0, 3.0])
+            .unwrap();
+        assert_eq!(rounded(model.coefficients()), vec![1.0, 0.5]);
+        let residuals = ModelKind::Logarithmic
+            .fit(&[0.0, 1.0, 2.0], &[0.0, 1.0, 2.0])
+            .unwrap()
+            .residuals(&[0.0], &[0.0]);
+        assert!(!residuals[0].is_finite());
+    }
+
+    #[test]
+    fn model_names() {
+        for (name, kind) in [
+            ("linear", ModelKind::Polynomial(1)),
+            ("quadratic", ModelKind::Polynomial(2)),
+            ("poly5", ModelKind::Polynomial(5)),
+            ("log", ModelKind::Logarithmic),
+            ("exp", ModelKind::Exponential),
+            ("power", ModelKind::Power),
+        ] {
+            assert_eq!(name.parse::<ModelKind>(), Ok(kind));
+            assert_eq!(kind.to_string(), name);
+        }
+        assert_eq!("Cubic".parse::<ModelKind>(), Ok(ModelKind::Polynomial(3)));
+        assert!("poly0".parse::<ModelKind>().is_err());
+        assert!("spline".parse::<ModelKind>().is_err());
+    }
+
+    #[test]
+    fn model_selection() {
+        let x_values: Vec<f64> = (1..=300).map(f64::from).collect();
+        // deterministic noise between -0.3 and 0.3
+        let noise = |i: usize| ((i * 7919) % 61) as f64 / 100.0 - 0.3;
+        let cubic: Vec<f64> = x_values
+            .iter()
+            .enumerate()
+            .map(|(i, x)| 0.000002 * x.powi(3) - 0.001 * x * x + 0.1 * x + noise(i))
+            .collect();
+        let log: Vec<f64> = x_values
+            .iter()
+            .enumerate()
+            .map(|(i, x)| 8.0 - 1.2 * x.ln() + noise(i))
+            .collect();
+
+        // ten moves a game
+        let game_ids: Vec<usize> = (0..300).map(|i| i / 10).collect();
+        for criterion in [Criterion::Cv, Criterion::Aic, Criterion::Bic] {
+            let ranked = rank_models(&game_ids, &x_values, &cubic, &CANDIDATES, 5, criterion);
+            assert_eq!(ranked.len(), CANDIDATES.len());
+            assert!(ranked
+                .windows(2)
+                .all(|w| w[0].get(criterion) <= w[1].get(criterion)));
+            let best = pick_model(None, &game_ids, &x_values, &log, 5, criterion).unwrap();
+            assert_eq!(best, ModelKind::Logarithmic);
+        }
+        let ranked = rank_models(&game_ids, &x_values, &cubic, &CANDIDATES, 5, Criterion::Bic);
+        assert_eq!(ranked[0].kind, ModelKind::Polynomial(3));
+
+        // a picked model is used as is
+        assert_eq!(
+            pick_model(
+                Some(ModelKind::Power),
+                &game_ids,
+                &x_values,
+                &cubic,
+                5,
+                Criterion::Cv
+            )
+            .unwrap(),
+            ModelKind::Power
+        );
+        // not enough points for anything but a line
+        let ranked = rank_models(
+            &[0, 1],
+            &[1.0, 2.0],
+            &[1.0, 3.0],
+            &CANDIDATES,
+            5,
+            Criterion::Cv,
+        );
+        let kinds: Vec<_> = ranked.iter().map(|score| score.kind).collect();
+        assert!(kinds.contains(&ModelKind::Polynomial(1)));
+        assert!(!kinds.contains(&ModelKind::Polynomial(2)));
+    }
+
+    #[test]
+    fn folds_by_game() {
+        // the moves of a game stay together, and the games take turns
+        let (fold_of, folds) = game_folds(&[7, 7, 3, 3, 3, 9, 7, 1], 3);
+        assert_eq!(folds, 3);
+        assert_eq!(fold_of, vec![0, 0, 1, 1, 1, 2, 0, 0]);
+        // no more folds than games
+        let (fold_of, folds) = game_folds(&[4, 4, 5, 5], 5);
+        assert_eq!(folds, 2);
+        assert_eq!(fold_of, vec![0, 0, 1, 1]);
+    }
+}
@@ -0,0 +1,193 @@
+// Time usage by move number and game phase
+// the rest of the analysis looks at time taken against the clock, this looks at it against
+// how far into the game the move was, so we can see where in a game people spend their time.
+
+use crate::moves::{MoveStore, TimeControl};
+use std::fmt;
+
+/// Summary of a set of times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// 90th percentile, the long thinks.
+    pub p90: f64,
+}
+
+impl Distribution {
+    /// `None` if there are no values.
+    pub fn of(values: &[f64]) -> Option<Distribution> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        // same nearest-rank percentile as the one variable analysis
+        let percentile =
+            |p: f64| sorted[((sorted.len() as f64 * p) as usize).min(sorted.len() - 1)];
+        Some(Distribution {
+            count: sorted.len(),
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            median: percentile(0.5),
+            p90: percentile(0.9),
+        })
+    }
+}
+
+/// Time left and time taken (see `MoveStore::xy`) for every move, indexed by move number starting at 1.
+/// Index 0 is always empty, and moves past `max_move` all go in the last entry.
+pub fn by_move_number(moves: &MoveStore, normalize: bool, max_move: u16) -> Vec<Vec<(f64, f64)>> {
+    let (x_values, y_values) = moves.xy(normalize);
+    let mut data = vec![Vec::new(); max_move as usize + 1];
+    for (record, point) in moves.iter().zip(x_values.into_iter().zip(y_values)) {
+        data[record.move_number().min(max_move) as usize].push(point);
+    }
+    data
+}
+
+/// Time taken for every move, by game phase (in the order of `moves::Phase::ALL`).
+/// Moves from games that couldn't be replayed are left out.
+pub fn by_phase(moves: &MoveStore, normalize: bool) -> [Vec<f64>; 3] {
+    let (_, y_values) = moves.xy(normalize);
+    let mut data = [Vec::new(), Vec::new(), Vec::new()];
+    for (record, time_taken) in moves.iter().zip(y_values) {
+        if let Some(phase) = record.context.phase() {
+            data[phase as usize].push(time_taken);
+        }
+    }
+    data
+}
+
+/// Moves played in one time control, by players in one rating band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Group {
+    pub time_control: TimeControl,
+    /// Lowest rating of the band, `None` for unrated players.
+    pub rating_band: Option<i32>,
+    pub band_width: i32,
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rating_band {
+            Some(min) => write!(
+                f,
+                "{}, {}-{} ELO",
+                self.time_control,
+                min,
+                min + self.band_width - 1
+            ),
+            None => write!(f, "{}, unrated", self.time_control),
+        }
+    }
+}
+
+/// Split the moves by time control and the mover's rating band (`band_width` points wide).
+/// Sorted by base time, increment and then rating.
+pub fn groups(moves: &MoveStore, band_width: i32) -> Vec<(Group, MoveStore)> {
+    let band_width = band_width.max(1);
+    let mut groups: Vec<(Group, MoveStore)> = Vec::new();
+    for record in moves.iter() {
+        let group = Group {
+            time_control: record.time_control,
+            rating_band: record
+                .rating()
+                .map(|rating| rating.div_euclid(band_width) * band_width),
+            band_width,
+        };
+        match groups.iter_mut().find(|(g, _)| *g == group) {
+            Some((_, store)) => store.push(record),
+            None => {
+                let mut store = MoveStore::new();
+                store.push(record);
+                groups.push((group, store));
+            }
+        }
+    }
+    groups.sort_by_key(|(g, _)| (g.time_control.base, g.time_control.increment, g.rating_band));
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{by_move_number, by_phase, groups, Distribution};
+    use crate::moves::{Color, MoveContext, MoveRecord, MoveStore};
+
+    fn record(ply: u16, time_spent: i32, elo: Option<i32>, pieces: Option<u8>) -> MoveRecord {
+        MoveRecord {
+            color: Color::of_ply(ply),
+            ply,
+            clock_after: 60_000 - time_spent,
+            time_spent,
+            white_elo: elo,
+            black_elo: elo.map(|elo| elo + 100),
+            context: MoveContext {
+                pieces,
+                ..MoveContext::default()
+            },
+            ..MoveRecord::example()
+        }
+    }
+
+    #[test]
+    fn distribution() {
+        assert_eq!(Distribution::of(&[]), None);
+        let values: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(
+            Distribution::of(&values),
+            Some(Distribution {
+                count: 10,
+                mean: 5.5,
+                median: 6.0,
+                p90: 10.0
+            })
+        );
+    }
+
+    #[test]
+    fn move_numbers_and_phases() {
+        let mut store = MoveStore::new();
+        for (ply, spent, pieces) in [
+            (1, 1_000, Some(14)),
+            (2, 2_000, Some(14)),
+            (3, 3_000, Some(8)),
+            (9, 4_000, Some(2)),
+            (10, 5_000, None),
+        ] {
+            store.push(record(ply, spent, Some(1500), pieces));
+        }
+        let data = by_move_number(&store, false, 3);
+        assert!(data[0].is_empty());
+        assert_eq!(data[1], vec![(59.0, 1.0), (58.0, 2.0)]);
+        assert_eq!(data[2], vec![(57.0, 3.0)]);
+        // move 5 goes in with the last move number
+        assert_eq!(data[3], vec![(56.0, 4.0), (55.0, 5.0)]);
+
+        assert_eq!(
+            by_phase(&store, false),
+            [vec![1.0, 2.0], vec![3.0], vec![4.0]]
+        );
+    }
+
+    #[test]
+    fn rating_groups() {
+        let mut store = MoveStore::new();
+        store.push(record(1, 1_000, Some(1450), None));
+        store.push(record(2, 1_000, Some(1450), None));
+        store.push(record(3, 1_000, None, None));
+        store.push(record(5, 1_000, Some(1399), None));
+        let groups = groups(&store, 200);
+        let names: Vec<_> = groups.iter().map(|(g, _)| g.to_string()).collect();
+        // black is 100 points higher, so in the same band as white's 1450
+        assert_eq!(
+            names,
+            vec![
+                "60+0, unrated",
+                "60+0, 1200-1399 ELO",
+                "60+0, 1400-1599 ELO"
+            ]
+        );
+        assert_eq!(groups[2].1.ply, vec![1, 2]);
+    }
+}